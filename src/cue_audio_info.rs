@@ -0,0 +1,515 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    cue_duration::CueDuration, cue_file_format::CueFileFormat, cue_file_resolver::ResolveError,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioContainer {
+    Wave,
+    Aiff,
+    Flac,
+    Mp3,
+    /// Headerless 16 bits stereo 44.1kHz samples, as BINARY and MOTOROLA files
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioInfo {
+    pub container: AudioContainer,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    /// Number of samples per channel
+    pub total_samples: u64,
+    /// Location of the samples for uncompressed integer PCM files
+    pub pcm: Option<PcmData>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcmData {
+    /// Offset of the first sample in the file
+    pub offset: u64,
+    pub big_endian: bool,
+}
+
+#[derive(Debug)]
+pub enum ProbeError {
+    Io(std::io::Error),
+    Resolve(ResolveError),
+    Unsupported,
+    Malformed(&'static str),
+}
+
+impl Display for ProbeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeError::Io(e) => write!(f, "{}", e),
+            ProbeError::Resolve(e) => write!(f, "{}", e),
+            ProbeError::Unsupported => write!(f, "unsupported audio file"),
+            ProbeError::Malformed(reason) => write!(f, "malformed audio file: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl From<std::io::Error> for ProbeError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<ResolveError> for ProbeError {
+    fn from(value: ResolveError) -> Self {
+        Self::Resolve(value)
+    }
+}
+
+impl AudioInfo {
+    /// Read the length and the sample format from the headers of the file at `path`.
+    ///
+    /// The container is detected from the file content. `format` is only used for
+    /// headerless BINARY and MOTOROLA files.
+    pub fn probe<P: AsRef<Path>>(path: P, format: CueFileFormat) -> Result<Self, ProbeError> {
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; 12];
+        let read = read_up_to(&mut reader, &mut magic)?;
+        let magic = &magic[..read];
+        reader.seek(SeekFrom::Start(0))?;
+
+        match magic {
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E']
+            | [b'R', b'F', b'6', b'4', _, _, _, _, b'W', b'A', b'V', b'E'] => {
+                probe_wave(&mut reader, file_len)
+            }
+            [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', _] => {
+                probe_aiff(&mut reader, file_len)
+            }
+            [b'f', b'L', b'a', b'C', ..] => probe_flac(&mut reader),
+            [b'I', b'D', b'3', ..] => {
+                let start = skip_id3v2(&mut reader)?;
+                let mut marker = [0u8; 4];
+                let read = read_up_to(&mut reader, &mut marker)?;
+                reader.seek(SeekFrom::Start(start))?;
+                match &marker[..read] {
+                    b"fLaC" => probe_flac(&mut reader),
+                    _ => probe_mp3(&mut reader, start, file_len),
+                }
+            }
+            [0xFF, second, ..] if second & 0xE0 == 0xE0 => probe_mp3(&mut reader, 0, file_len),
+            _ => match format {
+                CueFileFormat::BINARY | CueFileFormat::MOTOROLA => Ok(Self {
+                    container: AudioContainer::Raw,
+                    sample_rate: 44100,
+                    channels: 2,
+                    bits_per_sample: 16,
+                    total_samples: file_len / 4,
+                    pcm: Some(PcmData {
+                        offset: 0,
+                        big_endian: format == CueFileFormat::MOTOROLA,
+                    }),
+                }),
+                CueFileFormat::MP3 => probe_mp3(&mut reader, 0, file_len),
+                CueFileFormat::AIFF | CueFileFormat::WAVE => Err(ProbeError::Unsupported),
+            },
+        }
+    }
+
+    /// Length of the audio, truncated to a whole CD frame
+    pub fn duration(&self) -> CueDuration {
+        CueDuration::from_samples(self.total_samples, self.sample_rate)
+    }
+
+    /// Number of bytes of a sample for all the channels
+    pub fn block_align(&self) -> u64 {
+        self.channels as u64 * self.bits_per_sample.div_ceil(8) as u64
+    }
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize, ProbeError> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], ProbeError> {
+    let mut buffer = [0u8; N];
+    match read_up_to(reader, &mut buffer)? == N {
+        true => Ok(buffer),
+        false => Err(ProbeError::Malformed("unexpected end of file")),
+    }
+}
+
+/// Skip an ID3v2 tag and return the offset of the data following it.
+fn skip_id3v2<R: Read + Seek>(reader: &mut R) -> Result<u64, ProbeError> {
+    let header = read_array::<_, 10>(reader)?;
+    let size = header[6..10]
+        .iter()
+        .fold(0u64, |acc, byte| (acc << 7) | (*byte & 0x7F) as u64);
+    let footer = if header[5] & 0x10 != 0 { 10 } else { 0 };
+    let start = 10 + size + footer;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(start)
+}
+
+const WAVE_FORMAT_PCM: u16 = 0x0001;
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+/// GUID 00000001-0000-0010-8000-00AA00389B71 as stored in the file
+const KSDATAFORMAT_SUBTYPE_PCM: [u8; 16] = [
+    0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71,
+];
+
+fn probe_wave<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<AudioInfo, ProbeError> {
+    let _ = read_array::<_, 12>(reader)?;
+    let mut format = None;
+    let mut ds64_data_size = None;
+    loop {
+        let header = match read_array::<_, 8>(reader) {
+            Ok(header) => header,
+            Err(_) => return Err(ProbeError::Malformed("missing data chunk")),
+        };
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        let start = reader.stream_position()?;
+        match &header[0..4] {
+            b"fmt " => {
+                let fmt = read_array::<_, 16>(reader)?;
+                let audio_format = u16::from_le_bytes([fmt[0], fmt[1]]);
+                let channels = u16::from_le_bytes([fmt[2], fmt[3]]);
+                let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                let block_align = u16::from_le_bytes([fmt[12], fmt[13]]);
+                let bits_per_sample = u16::from_le_bytes([fmt[14], fmt[15]]);
+                let pcm = match audio_format {
+                    WAVE_FORMAT_PCM => true,
+                    // WAVE_FORMAT_EXTENSIBLE stores the actual format in its SubFormat GUID
+                    WAVE_FORMAT_EXTENSIBLE if size >= 40 => {
+                        let extension = read_array::<_, 24>(reader)?;
+                        let cb_size = u16::from_le_bytes([extension[0], extension[1]]);
+                        cb_size >= 22 && extension[8..24] == KSDATAFORMAT_SUBTYPE_PCM
+                    }
+                    _ => false,
+                };
+                format = Some((channels, sample_rate, block_align, bits_per_sample, pcm));
+            }
+            b"ds64" => {
+                let ds64 = read_array::<_, 16>(reader)?;
+                let mut data_size = [0u8; 8];
+                data_size.copy_from_slice(&ds64[8..16]);
+                ds64_data_size = Some(u64::from_le_bytes(data_size));
+            }
+            b"data" => {
+                let (channels, sample_rate, block_align, bits_per_sample, pcm) =
+                    format.ok_or(ProbeError::Malformed("data chunk before fmt chunk"))?;
+                if block_align == 0 || sample_rate == 0 {
+                    return Err(ProbeError::Malformed("invalid fmt chunk"));
+                }
+                let size = match (size, ds64_data_size) {
+                    (0xFFFF_FFFF, Some(data_size)) => data_size,
+                    (size, _) => size,
+                };
+                let available = file_len.saturating_sub(start);
+                return Ok(AudioInfo {
+                    container: AudioContainer::Wave,
+                    sample_rate,
+                    channels,
+                    bits_per_sample,
+                    total_samples: size.min(available) / block_align as u64,
+                    pcm: pcm.then_some(PcmData {
+                        offset: start,
+                        big_endian: false,
+                    }),
+                });
+            }
+            _ => (),
+        }
+        reader.seek(SeekFrom::Start(start + size + (size & 1)))?;
+    }
+}
+
+/// Convert an 80 bits IEEE 754 extended float, as used by AIFF sample rates.
+fn extended_to_u32(bytes: &[u8]) -> u32 {
+    let exponent = (u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7FFF) as i32;
+    let mut mantissa = [0u8; 8];
+    mantissa.copy_from_slice(&bytes[2..10]);
+    let mantissa = u64::from_be_bytes(mantissa);
+    let shift = 16383 + 63 - exponent;
+    match shift {
+        0..=63 => (mantissa >> shift) as u32,
+        _ => 0,
+    }
+}
+
+fn probe_aiff<R: Read + Seek>(reader: &mut R, file_len: u64) -> Result<AudioInfo, ProbeError> {
+    let form = read_array::<_, 12>(reader)?;
+    let aifc = &form[8..12] == b"AIFC";
+    let mut big_endian = true;
+    let mut common = None;
+    let mut available = None;
+    let mut pcm = None;
+    while common.is_none() || available.is_none() {
+        let header = match read_array::<_, 8>(reader) {
+            Ok(header) => header,
+            Err(_) => break,
+        };
+        let size = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        let start = reader.stream_position()?;
+        match &header[0..4] {
+            b"COMM" => {
+                let comm = read_array::<_, 18>(reader)?;
+                let channels = u16::from_be_bytes([comm[0], comm[1]]);
+                let frames = u32::from_be_bytes([comm[2], comm[3], comm[4], comm[5]]);
+                let bits_per_sample = u16::from_be_bytes([comm[6], comm[7]]);
+                let sample_rate = extended_to_u32(&comm[8..18]);
+                common = Some((channels, frames, bits_per_sample, sample_rate));
+                if aifc {
+                    // only uncompressed integer samples are read, in either byte order
+                    big_endian = match &read_array::<_, 4>(reader)? {
+                        b"NONE" | b"twos" => true,
+                        b"sowt" => false,
+                        _ => return Err(ProbeError::Unsupported),
+                    };
+                }
+            }
+            b"SSND" => {
+                let ssnd = read_array::<_, 8>(reader)?;
+                let offset = u32::from_be_bytes([ssnd[0], ssnd[1], ssnd[2], ssnd[3]]) as u64;
+                let data_start = start + 8 + offset;
+                pcm = Some(data_start);
+                let data_size = size.saturating_sub(8 + offset);
+                available = Some(data_size.min(file_len.saturating_sub(data_start)));
+            }
+            _ => (),
+        }
+        reader.seek(SeekFrom::Start(start + size + (size & 1)))?;
+    }
+    let (channels, frames, bits_per_sample, sample_rate) =
+        common.ok_or(ProbeError::Malformed("missing COMM chunk"))?;
+    let block_align = channels as u64 * bits_per_sample.div_ceil(8) as u64;
+    if block_align == 0 || sample_rate == 0 {
+        return Err(ProbeError::Malformed("invalid COMM chunk"));
+    }
+    let total_samples = match available {
+        Some(available) => (frames as u64).min(available / block_align),
+        None => 0,
+    };
+    Ok(AudioInfo {
+        container: AudioContainer::Aiff,
+        sample_rate,
+        channels,
+        bits_per_sample,
+        total_samples,
+        pcm: pcm.map(|offset| PcmData { offset, big_endian }),
+    })
+}
+
+fn probe_flac<R: Read + Seek>(reader: &mut R) -> Result<AudioInfo, ProbeError> {
+    let _ = read_array::<_, 4>(reader)?;
+    let header = read_array::<_, 4>(reader)?;
+    if header[0] & 0x7F != 0 {
+        return Err(ProbeError::Malformed(
+            "STREAMINFO is not the first metadata block",
+        ));
+    }
+    let streaminfo = read_array::<_, 34>(reader)?;
+    let mut packed = [0u8; 8];
+    packed.copy_from_slice(&streaminfo[10..18]);
+    let packed = u64::from_be_bytes(packed);
+    let total_samples = packed & 0xF_FFFF_FFFF;
+    if total_samples == 0 {
+        return Err(ProbeError::Malformed("unknown number of samples"));
+    }
+    let sample_rate = (packed >> 44) as u32;
+    if sample_rate == 0 {
+        return Err(ProbeError::Malformed("invalid STREAMINFO block"));
+    }
+    Ok(AudioInfo {
+        container: AudioContainer::Flac,
+        sample_rate,
+        channels: ((packed >> 41) & 0x7) as u16 + 1,
+        bits_per_sample: ((packed >> 36) & 0x1F) as u16 + 1,
+        total_samples,
+        pcm: None,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Mp3Frame {
+    mpeg1: bool,
+    mono: bool,
+    sample_rate: u32,
+    samples: u32,
+    length: u64,
+}
+
+fn parse_mp3_frame(header: [u8; 4]) -> Option<Mp3Frame> {
+    const BITRATES_V1: [[u32; 15]; 3] = [
+        [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+    ];
+    const BITRATES_V2: [[u32; 15]; 2] = [
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ];
+    if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+        return None;
+    }
+    let version = (header[1] >> 3) & 0x3;
+    let layer = (header[1] >> 1) & 0x3;
+    let bitrate_index = (header[2] >> 4) as usize;
+    let rate_index = ((header[2] >> 2) & 0x3) as usize;
+    let padding = ((header[2] >> 1) & 0x1) as u64;
+    if version == 1 || layer == 0 || bitrate_index == 0 || bitrate_index == 15 || rate_index == 3 {
+        return None;
+    }
+    let mpeg1 = version == 3;
+    let base_rate = [44100, 48000, 32000][rate_index];
+    let sample_rate = match version {
+        3 => base_rate,
+        2 => base_rate / 2,
+        _ => base_rate / 4,
+    };
+    // layer is 3 for Layer I, 2 for Layer II and 1 for Layer III
+    let bitrate = match (mpeg1, layer) {
+        (true, layer) => BITRATES_V1[3 - layer as usize][bitrate_index],
+        (false, 3) => BITRATES_V2[0][bitrate_index],
+        (false, _) => BITRATES_V2[1][bitrate_index],
+    } as u64
+        * 1000;
+    let samples = match (mpeg1, layer) {
+        (_, 3) => 384,
+        (false, 1) => 576,
+        _ => 1152,
+    };
+    let length = match layer {
+        3 => (12 * bitrate / sample_rate as u64 + padding) * 4,
+        _ => samples as u64 / 8 * bitrate / sample_rate as u64 + padding,
+    };
+    Some(Mp3Frame {
+        mpeg1,
+        mono: header[3] >> 6 == 3,
+        sample_rate,
+        samples,
+        length,
+    })
+}
+
+/// Find the first MPEG audio frame at or after `start`.
+fn find_mp3_frame<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+) -> Result<(u64, Mp3Frame), ProbeError> {
+    const SEARCH_LIMIT: usize = 64 * 1024;
+    reader.seek(SeekFrom::Start(start))?;
+    let mut buffer = vec![0u8; SEARCH_LIMIT + 3];
+    let read = read_up_to(reader, &mut buffer)?;
+    buffer
+        .windows(4)
+        .take(read.saturating_sub(3))
+        .enumerate()
+        .find_map(|(i, window)| {
+            parse_mp3_frame([window[0], window[1], window[2], window[3]])
+                .map(|frame| (start + i as u64, frame))
+        })
+        .ok_or(ProbeError::Malformed("no MPEG audio frame found"))
+}
+
+fn probe_mp3<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    file_len: u64,
+) -> Result<AudioInfo, ProbeError> {
+    let (offset, first) = find_mp3_frame(reader, start)?;
+    let info = |total_samples| AudioInfo {
+        container: AudioContainer::Mp3,
+        sample_rate: first.sample_rate,
+        channels: if first.mono { 1 } else { 2 },
+        bits_per_sample: 16,
+        total_samples,
+        pcm: None,
+    };
+
+    let side_info = match (first.mpeg1, first.mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    // a file too short for a Xing or VBRI header leaves zeros and falls back to the frame scan
+    reader.seek(SeekFrom::Start(offset + 4 + side_info))?;
+    let mut xing = [0u8; 120];
+    let _ = read_up_to(reader, &mut xing)?;
+    if &xing[0..4] == b"Xing" || &xing[0..4] == b"Info" {
+        let flags = u32::from_be_bytes([xing[4], xing[5], xing[6], xing[7]]);
+        if flags & 0x1 != 0 {
+            let frames = u32::from_be_bytes([xing[8], xing[9], xing[10], xing[11]]) as u64;
+            let lame_offset = 8 + [(0x1, 4), (0x2, 4), (0x4, 100), (0x8, 4)]
+                .iter()
+                .filter(|(flag, _)| flags & flag != 0)
+                .map(|(_, size)| size)
+                .sum::<usize>();
+            reader.seek(SeekFrom::Start(offset + 4 + side_info + lame_offset as u64))?;
+            let lame = read_array::<_, 24>(reader).ok();
+            let (delay, padding) = match lame {
+                Some(lame) if lame[0..4].iter().all(u8::is_ascii_alphanumeric) => {
+                    let delay = ((lame[21] as u64) << 4) | (lame[22] as u64 >> 4);
+                    let padding = (((lame[22] & 0x0F) as u64) << 8) | lame[23] as u64;
+                    (delay, padding)
+                }
+                _ => (0, 0),
+            };
+            let total = (frames * first.samples as u64).saturating_sub(delay + padding);
+            return Ok(info(total));
+        }
+    }
+
+    reader.seek(SeekFrom::Start(offset + 4 + 32))?;
+    let mut vbri = [0u8; 18];
+    let _ = read_up_to(reader, &mut vbri)?;
+    if &vbri[0..4] == b"VBRI" {
+        let frames = u32::from_be_bytes([vbri[14], vbri[15], vbri[16], vbri[17]]) as u64;
+        return Ok(info(frames * first.samples as u64));
+    }
+
+    let mut position = offset;
+    let mut total_samples = 0u64;
+    while position + 4 <= file_len {
+        reader.seek(SeekFrom::Start(position))?;
+        let frame = match read_array::<_, 4>(reader).ok().and_then(parse_mp3_frame) {
+            Some(frame) => frame,
+            None => break,
+        };
+        total_samples += frame.samples as u64;
+        position += frame.length;
+    }
+    Ok(info(total_samples))
+}
@@ -29,9 +29,40 @@ pub enum CueCdText {
     SizeInfo(String),
 }
 
-impl Display for CueCdText {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let (key, value) = match self {
+impl CueCdText {
+    /// CD-Text command `key` with `value`, ISRC is accepted for ISCR
+    pub fn from_key(key: &str, value: &str) -> Option<Self> {
+        let value = value.to_string();
+        let cd_text = match key.to_ascii_uppercase().as_str() {
+            "ARRANGER" => CueCdText::Arrager(value),
+            "COMPOSER" => CueCdText::Composer(value),
+            "DISC_ID" => CueCdText::DiscId(value),
+            "GENRE" => CueCdText::Genre(value),
+            "ISCR" | "ISRC" => CueCdText::ISrc(value),
+            "MESSAGE" => CueCdText::Message(value),
+            "PERFORMER" => CueCdText::Performer(value),
+            "SONGWRITER" => CueCdText::SongWriter(value),
+            "TITLE" => CueCdText::Title(value),
+            "TOC_INFO" => CueCdText::TocInfo(value),
+            "TOC_INFO2" => CueCdText::TocInfo2(value),
+            "UPC_EAN" => CueCdText::UpcEan(value),
+            "SIZE_INFO" => CueCdText::SizeInfo(value),
+            _ => return None,
+        };
+        Some(cd_text)
+    }
+
+    /// Keyword of the CD-Text command
+    pub fn key(&self) -> &'static str {
+        self.key_value().0
+    }
+
+    pub fn value(&self) -> &str {
+        self.key_value().1
+    }
+
+    fn key_value(&self) -> (&'static str, &str) {
+        match self {
             CueCdText::Arrager(n) => ("ARRANGER", n),
             CueCdText::Composer(n) => ("COMPOSER", n),
             CueCdText::DiscId(n) => ("DISC_ID", n),
@@ -45,8 +76,71 @@ impl Display for CueCdText {
             CueCdText::TocInfo2(n) => ("TOC_INFO2", n),
             CueCdText::UpcEan(n) => ("UPC_EAN", n),
             CueCdText::SizeInfo(n) => ("SIZE_INFO", n),
-        };
-        let value = cue_format_string_value(&value);
+        }
+    }
+}
+
+impl Display for CueCdText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (key, value) = self.key_value();
+        let value = cue_format_string_value(value);
         write!(f, "{} {}", key, value)
     }
 }
+
+/// Order in which the CD-Text commands of a scope are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CdTextOrder {
+    /// The order in which the values were first added
+    Insertion,
+    /// TITLE, PERFORMER, SONGWRITER, COMPOSER, ARRANGER, MESSAGE, then the
+    /// disc information, as most tools write them
+    #[default]
+    Conventional,
+    /// Alphabetical order of the keywords
+    Alphabetical,
+}
+
+impl CdTextOrder {
+    const CONVENTIONAL: [&'static str; 13] = [
+        "TITLE",
+        "PERFORMER",
+        "SONGWRITER",
+        "COMPOSER",
+        "ARRANGER",
+        "MESSAGE",
+        "GENRE",
+        "DISC_ID",
+        "ISCR",
+        "UPC_EAN",
+        "TOC_INFO",
+        "TOC_INFO2",
+        "SIZE_INFO",
+    ];
+
+    /// `cd_texts`, stored in insertion order, sorted in this order
+    pub(crate) fn sort<'a>(&self, cd_texts: &'a [CueCdText]) -> Vec<&'a CueCdText> {
+        let mut sorted = cd_texts.iter().collect::<Vec<_>>();
+        match self {
+            CdTextOrder::Insertion => (),
+            CdTextOrder::Conventional => sorted.sort_by_key(|cd_text| {
+                Self::CONVENTIONAL
+                    .iter()
+                    .position(|key| *key == cd_text.key())
+            }),
+            CdTextOrder::Alphabetical => sorted.sort_by_key(|cd_text| cd_text.key()),
+        }
+        sorted
+    }
+}
+
+/// Add `cd_text` to `cd_texts`, replacing the value of the same keyword in place
+pub(crate) fn set_cd_text(cd_texts: &mut Vec<CueCdText>, cd_text: CueCdText) {
+    match cd_texts
+        .iter_mut()
+        .find(|other| other.key() == cd_text.key())
+    {
+        Some(other) => *other = cd_text,
+        None => cd_texts.push(cd_text),
+    }
+}
@@ -0,0 +1,97 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Keywords written in the CD-Text block of a scope
+const CD_TEXT_KEYWORDS: [&str; 14] = [
+    "ARRANGER",
+    "COMPOSER",
+    "DISC_ID",
+    "GENRE",
+    "ISCR",
+    "ISRC",
+    "MESSAGE",
+    "PERFORMER",
+    "SONGWRITER",
+    "TITLE",
+    "TOC_INFO",
+    "TOC_INFO2",
+    "UPC_EAN",
+    "SIZE_INFO",
+];
+
+/// Place of an unknown command in its scope, relative to the commands the crate writes
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CommandPosition {
+    /// Before every other line of the scope
+    Start,
+    /// After the lines of the command `keyword`, such as `FLAGS` or `FILE`.
+    ///
+    /// Every CD-Text keyword stands for the whole CD-Text block, and a keyword that
+    /// is not written in the scope is the same as [`CommandPosition::End`].
+    After(String),
+    /// After every other line of the scope, which is after the last track for the sheet
+    End,
+}
+
+/// A line the crate does not model, such as a vendor extension, written back as it is
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnknownCommand {
+    line: String,
+    position: CommandPosition,
+}
+
+impl UnknownCommand {
+    pub fn new(line: &str, position: CommandPosition) -> Self {
+        Self {
+            line: line.trim().to_string(),
+            position,
+        }
+    }
+
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    pub fn position(&self) -> &CommandPosition {
+        &self.position
+    }
+
+    /// Section of the scope the command is written after, among `sections`
+    fn section<'a>(&self, sections: &[&'a str]) -> &'a str {
+        let keyword = match &self.position {
+            CommandPosition::Start => return "START",
+            CommandPosition::End => return "END",
+            CommandPosition::After(keyword) => keyword.to_ascii_uppercase(),
+        };
+        let keyword = match CD_TEXT_KEYWORDS.contains(&keyword.as_str()) {
+            true => "CDTEXT".to_string(),
+            false => keyword,
+        };
+        sections
+            .iter()
+            .find(|section| **section == keyword)
+            .copied()
+            .unwrap_or("END")
+    }
+}
+
+/// Lines of the commands placed in `section`, one of `sections` or `START` and `END`
+pub(crate) fn repr_unknown_commands(
+    commands: &[UnknownCommand],
+    sections: &[&str],
+    section: &str,
+    indent: &str,
+) -> String {
+    commands
+        .iter()
+        .filter(|command| command.section(sections) == section)
+        .map(|command| format!("{}{}\n", indent, command.line))
+        .collect()
+}
@@ -0,0 +1,295 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::fmt::Display;
+
+use crate::{
+    cue_cd_text::set_cd_text, cue_duration::CueDuration, cue_file_format::CueFileFormat,
+    cue_metadata::TRACK_ONLY_KEYS, cue_rem::RemEntry, cue_sheet::CueSheet,
+};
+
+/// How the audio of the concatenated sheets is laid out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConcatLayout<'a> {
+    /// Every sheet keeps its own files, a FILE command starts each of them
+    MultiFile,
+    /// The single-image sheets are joined into the image `filename`. `lengths` holds
+    /// the length of the file of every sheet, all of them but the last one are needed.
+    SingleImage {
+        filename: &'a str,
+        format: CueFileFormat,
+        lengths: &'a [CueDuration],
+    },
+}
+
+/// What to do when the sheets disagree on a disc-level value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the value of the first sheet that has one
+    KeepFirst,
+    /// Fail with [`ConcatError::Conflict`]
+    Reject,
+    /// Drop the disc value and give each track the value of its sheet, unless it has
+    /// its own. Values that tracks do not inherit, such as TITLE, keep the first one.
+    MoveToTracks,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConcatError {
+    NoSheet,
+    /// The sheet at this position references more than one file
+    NotSingleImage(usize),
+    /// The length of the file of the sheet at this position is needed
+    MissingLength(usize),
+    /// The sheets have different values for this disc-level command
+    Conflict(String),
+    /// The sheets have more than 99 tracks in total
+    TooManyTracks,
+}
+
+impl Display for ConcatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConcatError::NoSheet => write!(f, "no sheet to concatenate"),
+            ConcatError::NotSingleImage(i) => {
+                write!(f, "sheet {} references more than one file", i + 1)
+            }
+            ConcatError::MissingLength(i) => write!(f, "unknown length for sheet {}", i + 1),
+            ConcatError::Conflict(key) => write!(f, "the sheets have different {} values", key),
+            ConcatError::TooManyTracks => write!(f, "the sheets have more than 99 tracks"),
+        }
+    }
+}
+
+impl std::error::Error for ConcatError {}
+
+/// Disc-level values of one command, sheet by sheet
+struct DiscValues<'a, V> {
+    key: &'a str,
+    values: Vec<Vec<V>>,
+}
+
+impl<'a, V: Copy + PartialEq> DiscValues<'a, V> {
+    /// Keys in order of appearance, with the values of every sheet
+    fn collect<F, I>(sheets: &'a [CueSheet], entries: F) -> Vec<Self>
+    where
+        F: Fn(&'a CueSheet) -> I,
+        I: Iterator<Item = (&'a str, V)>,
+    {
+        let mut keys = Vec::<&str>::new();
+        for (key, _) in sheets.iter().flat_map(&entries) {
+            if !keys.iter().any(|other| other.eq_ignore_ascii_case(key)) {
+                keys.push(key)
+            }
+        }
+        keys.into_iter()
+            .map(|key| DiscValues {
+                key,
+                values: sheets
+                    .iter()
+                    .map(|sheet| {
+                        entries(sheet)
+                            .filter(|(other, _)| other.eq_ignore_ascii_case(key))
+                            .map(|(_, value)| value)
+                            .collect()
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Values of the first sheet that has some, `None` if the policy moves them to the tracks
+    fn merge(&self, policy: MergePolicy) -> Result<Option<Vec<V>>, ConcatError> {
+        let mut present = self.values.iter().filter(|values| !values.is_empty());
+        let first = present.next().cloned().unwrap_or_default();
+        let conflict = present.any(|values| *values != first);
+        let inherited = !TRACK_ONLY_KEYS.contains(&self.key.to_ascii_uppercase().as_str());
+        match (conflict, policy) {
+            (false, _) | (true, MergePolicy::KeepFirst) => Ok(Some(first)),
+            (true, MergePolicy::Reject) => Err(ConcatError::Conflict(self.key.to_string())),
+            (true, MergePolicy::MoveToTracks) if inherited => Ok(None),
+            (true, MergePolicy::MoveToTracks) => Ok(Some(first)),
+        }
+    }
+}
+
+impl CueSheet {
+    /// Append `sheets` one after the other into a single sheet.
+    ///
+    /// Tracks are numbered from 1 and there can be at most 99 of them. The disc
+    /// metadata is merged with `policy`, REM lines without value like the others, and
+    /// the REM DISCNUMBER of each sheet becomes a REM of its tracks. CDTEXTFILE is the
+    /// one of the first sheet.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn concat(
+        sheets: &[CueSheet],
+        layout: ConcatLayout,
+        policy: MergePolicy,
+    ) -> Result<CueSheet, ConcatError> {
+        let first = sheets.first().ok_or(ConcatError::NoSheet)?;
+        if sheets
+            .iter()
+            .map(|sheet| sheet.tracks().count())
+            .sum::<usize>()
+            > 99
+        {
+            return Err(ConcatError::TooManyTracks);
+        }
+        let mut offsets = vec![CueDuration::zero()];
+        if let ConcatLayout::SingleImage { lengths, .. } = layout {
+            for (i, sheet) in sheets.iter().enumerate() {
+                if sheet.files().len() > 1 {
+                    return Err(ConcatError::NotSingleImage(i));
+                }
+                if i + 1 < sheets.len() {
+                    let length = lengths.get(i).ok_or(ConcatError::MissingLength(i))?;
+                    offsets.push(offsets[i] + *length);
+                }
+            }
+        }
+
+        let mut tracks = sheets
+            .iter()
+            .enumerate()
+            .map(|(i, sheet)| {
+                sheet
+                    .tracks()
+                    .enumerate()
+                    .map(|(j, track)| {
+                        let mut track = track.clone();
+                        for number in sheet.rem_values("DISCNUMBER") {
+                            if track.rem("DISCNUMBER").is_none() {
+                                let _ = track.add_rem("DISCNUMBER", number);
+                            }
+                        }
+                        match layout {
+                            ConcatLayout::MultiFile if i > 0 && j == 0 => {
+                                if track.file().is_none() {
+                                    let file = (sheet.file_name().to_string(), sheet.file_format());
+                                    let _ = track.set_file(Some(file));
+                                }
+                            }
+                            ConcatLayout::MultiFile => (),
+                            ConcatLayout::SingleImage { .. } => {
+                                for position in track.indexes_mut().values_mut() {
+                                    *position = offsets[i] + *position;
+                                }
+                            }
+                        }
+                        track
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut sheet = first.clone();
+        if let ConcatLayout::SingleImage {
+            filename, format, ..
+        } = layout
+        {
+            let _ = sheet.set_file(filename, format);
+        }
+        sheet.cd_texts_mut().clear();
+        let cd_texts = DiscValues::collect(sheets, |sheet| {
+            sheet
+                .cd_texts()
+                .map(|cd_text| (cd_text.key(), cd_text.value()))
+        });
+        for values in cd_texts {
+            let cd_text = |i: usize| {
+                sheets[i]
+                    .cd_texts()
+                    .find(|cd_text| cd_text.key() == values.key)
+            };
+            match values.merge(policy)? {
+                Some(_) => {
+                    let cd_text = (0..sheets.len()).find_map(cd_text).cloned();
+                    if let Some(cd_text) = cd_text {
+                        set_cd_text(sheet.cd_texts_mut(), cd_text);
+                    }
+                }
+                None => {
+                    for (i, tracks) in tracks.iter_mut().enumerate() {
+                        let Some(cd_text) = cd_text(i) else {
+                            continue;
+                        };
+                        let tracks = tracks
+                            .iter_mut()
+                            .filter(|t| t.cd_text(values.key).is_none());
+                        for track in tracks {
+                            set_cd_text(track.cd_texts_mut(), cd_text.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        sheet.rems_mut().clear();
+        let rems = DiscValues::collect(sheets, |sheet| {
+            sheet
+                .rems()
+                .filter(|rem| !rem.is("DISCNUMBER"))
+                .map(|rem| (rem.key(), rem.value()))
+        });
+        for values in rems {
+            match values.merge(policy)? {
+                Some(merged) => sheet
+                    .rems_mut()
+                    .extend(merged.iter().map(|value| RemEntry::new(values.key, *value))),
+                None => {
+                    for (tracks, disc_values) in tracks.iter_mut().zip(&values.values) {
+                        let tracks = tracks
+                            .iter_mut()
+                            .filter(|t| !t.rems().any(|rem| rem.is(values.key)));
+                        for track in tracks {
+                            for value in disc_values {
+                                let _ = match value {
+                                    Some(value) => track.add_rem(values.key, value),
+                                    None => track.add_bare_rem(values.key),
+                                };
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let catalogs = DiscValues::collect(sheets, |sheet| {
+            sheet
+                .catalog()
+                .map(|catalog| ("CATALOG", catalog))
+                .into_iter()
+        });
+        if let Some(values) = catalogs.first() {
+            *sheet.catalog_mut() = match values.merge(policy)? {
+                Some(merged) => merged.first().map(|catalog| catalog.to_string()),
+                None => None,
+            };
+        }
+        for other in &sheets[1..] {
+            for command in other.unknown_commands() {
+                if !sheet.unknown_commands().any(|known| known == command) {
+                    sheet.unknown_commands_mut().push(command.clone());
+                }
+            }
+        }
+
+        *sheet.tracks_mut() = tracks
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(i, mut track)| {
+                let _ = track.set_number(i as u32 + 1);
+                track
+            })
+            .collect();
+        Ok(sheet)
+    }
+}
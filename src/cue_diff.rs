@@ -0,0 +1,594 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use crate::{
+    cue_cd_text::CueCdText, cue_duration::CueDuration, cue_file_format::CueFileFormat,
+    cue_rem::RemEntry, cue_sheet::CueSheet, cue_track::CueTrack, cue_track_flag::CueTrackFlag,
+    cue_track_mode::CueTrackMode,
+};
+
+/// Part of the sheet a change applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiffScope {
+    Sheet,
+    Track(u32),
+}
+
+impl Display for DiffScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffScope::Sheet => write!(f, "SHEET"),
+            DiffScope::Track(track) => write!(f, "TRACK {:02}", track),
+        }
+    }
+}
+
+/// One difference between two sheets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CueChange {
+    /// The track only exists in the new sheet, its content follows as changes from an empty track
+    TrackAdded {
+        track: u32,
+        mode: CueTrackMode,
+    },
+    TrackRemoved(u32),
+    /// The track is matched with a track of another number in the new sheet, the
+    /// following changes use the new number
+    TrackRenumbered {
+        old: u32,
+        new: u32,
+    },
+    CatalogChanged {
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// FILE of the sheet, or FILE written before a TRACK
+    FileChanged {
+        scope: DiffScope,
+        old: Option<(String, CueFileFormat)>,
+        new: Option<(String, CueFileFormat)>,
+    },
+    /// FILE written before an INDEX inside a track
+    IndexFileChanged {
+        track: u32,
+        index: u32,
+        old: Option<(String, CueFileFormat)>,
+        new: Option<(String, CueFileFormat)>,
+    },
+    ModeChanged {
+        track: u32,
+        old: CueTrackMode,
+        new: CueTrackMode,
+    },
+    CdTextChanged {
+        scope: DiffScope,
+        key: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// Values of every REM entry `key`, in order
+    RemChanged {
+        scope: DiffScope,
+        key: String,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    /// Text of every REM line without value, in order
+    BareRemsChanged {
+        scope: DiffScope,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    FlagsChanged {
+        track: u32,
+        old: Vec<CueTrackFlag>,
+        new: Vec<CueTrackFlag>,
+    },
+    PregapChanged {
+        track: u32,
+        old: Option<CueDuration>,
+        new: Option<CueDuration>,
+    },
+    PostgapChanged {
+        track: u32,
+        old: Option<CueDuration>,
+        new: Option<CueDuration>,
+    },
+    IndexAdded {
+        track: u32,
+        index: u32,
+        position: CueDuration,
+    },
+    IndexRemoved {
+        track: u32,
+        index: u32,
+        position: CueDuration,
+    },
+    IndexMoved {
+        track: u32,
+        index: u32,
+        old: CueDuration,
+        new: CueDuration,
+        /// Move in frames, negative when the INDEX comes earlier
+        delta: i64,
+    },
+}
+
+fn file_or_none(file: &Option<(String, CueFileFormat)>) -> String {
+    or_none(
+        &file
+            .as_ref()
+            .map(|(name, format)| format!("{} {}", name, format)),
+    )
+}
+
+fn or_none<T: Display>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map_or("none".to_string(), |value| format!("\"{}\"", value))
+}
+
+fn list<T: Display>(values: &[T]) -> String {
+    let values = values.iter().map(|value| format!("\"{}\"", value));
+    format!("[{}]", values.collect::<Vec<_>>().join(", "))
+}
+
+impl Display for CueChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CueChange::TrackAdded { track, mode } => {
+                write!(f, "TRACK {:02} {} added", track, mode)
+            }
+            CueChange::TrackRemoved(track) => write!(f, "TRACK {:02} removed", track),
+            CueChange::TrackRenumbered { old, new } => {
+                write!(f, "TRACK {:02} renumbered to TRACK {:02}", old, new)
+            }
+            CueChange::CatalogChanged { old, new } => {
+                write!(f, "SHEET CATALOG: {} -> {}", or_none(old), or_none(new))
+            }
+            CueChange::FileChanged { scope, old, new } => {
+                write!(
+                    f,
+                    "{} FILE: {} -> {}",
+                    scope,
+                    file_or_none(old),
+                    file_or_none(new)
+                )
+            }
+            CueChange::IndexFileChanged {
+                track,
+                index,
+                old,
+                new,
+            } => write!(
+                f,
+                "TRACK {:02} FILE before INDEX {:02}: {} -> {}",
+                track,
+                index,
+                file_or_none(old),
+                file_or_none(new)
+            ),
+            CueChange::ModeChanged { track, old, new } => {
+                write!(f, "TRACK {:02} mode: {} -> {}", track, old, new)
+            }
+            CueChange::CdTextChanged {
+                scope,
+                key,
+                old,
+                new,
+            } => write!(f, "{} {}: {} -> {}", scope, key, or_none(old), or_none(new)),
+            CueChange::RemChanged {
+                scope,
+                key,
+                old,
+                new,
+            } => write!(f, "{} REM {}: {} -> {}", scope, key, list(old), list(new)),
+            CueChange::BareRemsChanged { scope, old, new } => {
+                write!(
+                    f,
+                    "{} REM without value: {} -> {}",
+                    scope,
+                    list(old),
+                    list(new)
+                )
+            }
+            CueChange::FlagsChanged { track, old, new } => {
+                write!(
+                    f,
+                    "TRACK {:02} FLAGS: {} -> {}",
+                    track,
+                    list(old),
+                    list(new)
+                )
+            }
+            CueChange::PregapChanged { track, old, new } => {
+                write!(
+                    f,
+                    "TRACK {:02} PREGAP: {} -> {}",
+                    track,
+                    or_none(old),
+                    or_none(new)
+                )
+            }
+            CueChange::PostgapChanged { track, old, new } => {
+                write!(
+                    f,
+                    "TRACK {:02} POSTGAP: {} -> {}",
+                    track,
+                    or_none(old),
+                    or_none(new)
+                )
+            }
+            CueChange::IndexAdded {
+                track,
+                index,
+                position,
+            } => write!(
+                f,
+                "TRACK {:02} INDEX {:02} added at {}",
+                track, index, position
+            ),
+            CueChange::IndexRemoved {
+                track,
+                index,
+                position,
+            } => write!(
+                f,
+                "TRACK {:02} INDEX {:02} removed from {}",
+                track, index, position
+            ),
+            CueChange::IndexMoved {
+                track,
+                index,
+                old,
+                new,
+                delta,
+            } => write!(
+                f,
+                "TRACK {:02} INDEX {:02} moved by {:+} frames: {} -> {}",
+                track, index, delta, old, new
+            ),
+        }
+    }
+}
+
+/// CD-Text values by keyword, REM values by key and REM lines without value of a scope
+type Metadata<'a> = (
+    BTreeMap<&'a str, &'a str>,
+    BTreeMap<String, Vec<String>>,
+    Vec<String>,
+);
+
+fn metadata<'a>(
+    cd_texts: impl Iterator<Item = &'a CueCdText>,
+    rems: impl Iterator<Item = &'a RemEntry>,
+) -> Metadata<'a> {
+    let cd_texts = cd_texts
+        .map(|cd_text| (cd_text.key(), cd_text.value()))
+        .collect();
+    let mut values = BTreeMap::<String, Vec<String>>::new();
+    let mut bare = Vec::new();
+    for rem in rems {
+        match rem.value() {
+            Some(value) => values
+                .entry(rem.key().to_ascii_uppercase())
+                .or_default()
+                .push(value.to_string()),
+            None => bare.push(rem.key().to_string()),
+        }
+    }
+    (cd_texts, values, bare)
+}
+
+/// What a track is matched on between two sheets: its mode, CD-Text values and REM entries
+fn track_key(track: &CueTrack) -> (CueTrackMode, Vec<(&str, &str)>, Vec<&RemEntry>) {
+    let mut cd_texts = track
+        .cd_texts()
+        .map(|cd_text| (cd_text.key(), cd_text.value()))
+        .collect::<Vec<_>>();
+    cd_texts.sort();
+    (track.mode(), cd_texts, track.rems().collect())
+}
+
+/// Old and new places of the tracks, `None` for a removed or an added track.
+///
+/// Tracks with the same key are matched so that as many as possible keep their order,
+/// and the tracks left between two matches are paired in order.
+fn match_tracks(old: &[&CueTrack], new: &[&CueTrack]) -> Vec<(Option<usize>, Option<usize>)> {
+    let old_keys = old.iter().map(|track| track_key(track)).collect::<Vec<_>>();
+    let new_keys = new.iter().map(|track| track_key(track)).collect::<Vec<_>>();
+    let (n, m) = (old.len(), new.len());
+    // common[i][j] is the longest common sequence of old[i..] and new[j..]
+    let mut common = vec![vec![0; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = match old_keys[i] == new_keys[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut old_gap, mut new_gap) = (Vec::new(), Vec::new());
+    let flush = |pairs: &mut Vec<_>, old_gap: &mut Vec<usize>, new_gap: &mut Vec<usize>| {
+        let len = old_gap.len().max(new_gap.len());
+        for k in 0..len {
+            pairs.push((old_gap.get(k).copied(), new_gap.get(k).copied()))
+        }
+        old_gap.clear();
+        new_gap.clear();
+    };
+    // on a tie, skip the track with the lower place to stay close to pairing by place
+    let skip_old = |left_old: usize, left_new: usize, behind: bool| {
+        left_old > left_new || (left_old == left_new && behind)
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_keys[i] == new_keys[j] {
+            flush(&mut pairs, &mut old_gap, &mut new_gap);
+            pairs.push((Some(i), Some(j)));
+            (i, j) = (i + 1, j + 1);
+        } else if j == m || (i < n && skip_old(common[i + 1][j], common[i][j + 1], i <= j)) {
+            old_gap.push(i);
+            i += 1;
+        } else {
+            new_gap.push(j);
+            j += 1;
+        }
+    }
+    flush(&mut pairs, &mut old_gap, &mut new_gap);
+    pairs
+}
+
+/// Tracks in order, with the absolute position of their INDEX
+fn tracks_positions(sheet: &CueSheet, sum: bool) -> Vec<(&CueTrack, BTreeMap<u32, CueDuration>)> {
+    sheet.tracks().zip(sheet.index_positions(sum)).collect()
+}
+
+/// Changes from one sheet to another, see [`CueSheet::diff`]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CueDiff {
+    changes: Vec<CueChange>,
+    sum: bool,
+}
+
+impl CueDiff {
+    pub fn changes(&self) -> &[CueChange] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Whether INDEX values were read as durations, see [`CueSheet::diff`]
+    pub fn sum(&self) -> bool {
+        self.sum
+    }
+
+    fn push_if<T: PartialEq>(&mut self, old: T, new: T, change: impl FnOnce(T, T) -> CueChange) {
+        if old != new {
+            self.changes.push(change(old, new))
+        }
+    }
+
+    /// Changes of the CD-Text values and REM entries of a scope
+    fn diff_metadata(&mut self, scope: DiffScope, old: Metadata, new: Metadata) {
+        let keys = old.0.keys().chain(new.0.keys());
+        for key in keys.collect::<BTreeSet<_>>() {
+            let old = old.0.get(key).map(|value| value.to_string());
+            let new = new.0.get(key).map(|value| value.to_string());
+            self.push_if(old, new, |old, new| CueChange::CdTextChanged {
+                scope,
+                key: key.to_string(),
+                old,
+                new,
+            });
+        }
+        let keys = old.1.keys().chain(new.1.keys());
+        for key in keys.collect::<BTreeSet<_>>() {
+            let old = old.1.get(key).cloned().unwrap_or_default();
+            let new = new.1.get(key).cloned().unwrap_or_default();
+            self.push_if(old, new, |old, new| CueChange::RemChanged {
+                scope,
+                key: key.to_string(),
+                old,
+                new,
+            });
+        }
+        self.push_if(old.2, new.2, |old, new| CueChange::BareRemsChanged {
+            scope,
+            old,
+            new,
+        });
+    }
+
+    fn diff_track(
+        &mut self,
+        old: &CueTrack,
+        old_positions: &BTreeMap<u32, CueDuration>,
+        new: &CueTrack,
+        new_positions: &BTreeMap<u32, CueDuration>,
+    ) {
+        let track = new.number();
+        let file = |track: &CueTrack| {
+            track
+                .file()
+                .map(|(name, format)| (name.to_string(), format))
+        };
+        self.push_if(file(old), file(new), |old, new| CueChange::FileChanged {
+            scope: DiffScope::Track(track),
+            old,
+            new,
+        });
+        let indexes = old.index_files().keys().chain(new.index_files().keys());
+        for index in indexes.copied().collect::<BTreeSet<_>>() {
+            let old = old.index_files().get(&index).cloned();
+            let new = new.index_files().get(&index).cloned();
+            self.push_if(old, new, |old, new| CueChange::IndexFileChanged {
+                track,
+                index,
+                old,
+                new,
+            });
+        }
+        self.push_if(old.mode(), new.mode(), |old, new| CueChange::ModeChanged {
+            track,
+            old,
+            new,
+        });
+        self.diff_metadata(
+            DiffScope::Track(track),
+            metadata(old.cd_texts(), old.rems()),
+            metadata(new.cd_texts(), new.rems()),
+        );
+        let flags = |track: &CueTrack| track.flags().copied().collect::<Vec<_>>();
+        self.push_if(flags(old), flags(new), |old, new| CueChange::FlagsChanged {
+            track,
+            old,
+            new,
+        });
+        self.push_if(old.pregap(), new.pregap(), |old, new| {
+            CueChange::PregapChanged { track, old, new }
+        });
+        self.push_if(old.postgap(), new.postgap(), |old, new| {
+            CueChange::PostgapChanged { track, old, new }
+        });
+
+        let indexes = old_positions.keys().chain(new_positions.keys());
+        for index in indexes.copied().collect::<BTreeSet<_>>() {
+            let change = match (old_positions.get(&index), new_positions.get(&index)) {
+                (Some(old), Some(new)) if old != new => CueChange::IndexMoved {
+                    track,
+                    index,
+                    old: *old,
+                    new: *new,
+                    delta: new.frames() as i64 - old.frames() as i64,
+                },
+                (None, Some(position)) => CueChange::IndexAdded {
+                    track,
+                    index,
+                    position: *position,
+                },
+                (Some(position), None) => CueChange::IndexRemoved {
+                    track,
+                    index,
+                    position: *position,
+                },
+                _ => continue,
+            };
+            self.changes.push(change)
+        }
+    }
+}
+
+impl Display for CueDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        Ok(())
+    }
+}
+
+impl CueSheet {
+    /// Changes that turn `self` into `other`.
+    ///
+    /// Tracks are matched on their mode, CD-Text values and REM entries, so that a track
+    /// inserted or removed in the middle renumbers the following ones instead of changing
+    /// all of them. Removed and renumbered tracks come first, in an order that can be
+    /// applied one change at a time. CD-Text values are compared by keyword, so their
+    /// order does not matter, REM entries by key with the values of a key in order, and
+    /// REM lines without value in order. CDTEXTFILE and unknown commands are not compared.
+    /// If `sum` is set, INDEX values are read as durations like in [`CueSheet::repr`],
+    /// and the positions they lead to are compared.
+    pub fn diff(&self, other: &CueSheet, sum: bool) -> CueDiff {
+        let mut diff = CueDiff {
+            sum,
+            ..Default::default()
+        };
+        let catalog = |sheet: &CueSheet| sheet.catalog().map(str::to_string);
+        diff.push_if(catalog(self), catalog(other), |old, new| {
+            CueChange::CatalogChanged { old, new }
+        });
+        let file = |sheet: &CueSheet| Some((sheet.file_name().to_string(), sheet.file_format()));
+        diff.push_if(file(self), file(other), |old, new| CueChange::FileChanged {
+            scope: DiffScope::Sheet,
+            old,
+            new,
+        });
+        diff.diff_metadata(
+            DiffScope::Sheet,
+            metadata(self.cd_texts(), self.rems()),
+            metadata(other.cd_texts(), other.rems()),
+        );
+
+        let old_tracks = tracks_positions(self, sum);
+        let new_tracks = tracks_positions(other, sum);
+        let pairs = match_tracks(
+            &old_tracks
+                .iter()
+                .map(|(track, _)| *track)
+                .collect::<Vec<_>>(),
+            &new_tracks
+                .iter()
+                .map(|(track, _)| *track)
+                .collect::<Vec<_>>(),
+        );
+        for (old, _) in pairs.iter().filter(|(_, new)| new.is_none()) {
+            let number = old_tracks[old.unwrap()].0.number();
+            diff.changes.push(CueChange::TrackRemoved(number))
+        }
+        // tracks moving down go first from the lowest, then tracks moving up from the
+        // highest, so that no track takes a number still in use
+        let (mut up, down): (Vec<_>, Vec<_>) = pairs
+            .iter()
+            .filter_map(|pair| match pair {
+                (Some(old), Some(new)) => {
+                    Some((old_tracks[*old].0.number(), new_tracks[*new].0.number()))
+                }
+                _ => None,
+            })
+            .filter(|(old, new)| old != new)
+            .partition(|(old, new)| new > old);
+        up.reverse();
+        for (old, new) in down.into_iter().chain(up) {
+            diff.changes.push(CueChange::TrackRenumbered { old, new })
+        }
+
+        let empty = BTreeMap::new();
+        for pair in pairs {
+            match pair {
+                (Some(old), Some(new)) => {
+                    let (old, old_positions) = &old_tracks[old];
+                    let (new, new_positions) = &new_tracks[new];
+                    diff.diff_track(old, old_positions, new, new_positions)
+                }
+                (None, Some(new)) => {
+                    let (new, new_positions) = &new_tracks[new];
+                    diff.changes.push(CueChange::TrackAdded {
+                        track: new.number(),
+                        mode: new.mode(),
+                    });
+                    let old = CueTrack::new(new.number(), new.mode());
+                    diff.diff_track(&old, &empty, new, new_positions)
+                }
+                _ => (),
+            }
+        }
+        diff
+    }
+}
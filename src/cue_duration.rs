@@ -8,7 +8,10 @@
 //                                                                                            //
 // /////////////////////////////////////////////////////////////////////////////////////////////
 
-use std::{fmt::Display, ops::Add};
+use std::{cmp::Ordering, fmt::Display, ops::Add};
+
+/// Number of CD frames in a second
+pub const FRAMES_PER_SECOND: u32 = 75;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DurationFormat {
@@ -16,7 +19,7 @@ pub enum DurationFormat {
     MinSecMil(u32, u32, u32),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy)]
 pub struct CueDuration {
     min: u32,
     sec: u32,
@@ -54,11 +57,37 @@ impl Add for CueDuration {
         Self {
             min: minutes,
             sec: seconds,
-            frame: frame,
+            frame,
         }
     }
 }
 
+impl PartialEq for CueDuration {
+    fn eq(&self, other: &Self) -> bool {
+        self.frames() == other.frames()
+    }
+}
+
+impl Eq for CueDuration {}
+
+impl std::hash::Hash for CueDuration {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.frames().hash(state)
+    }
+}
+
+impl PartialOrd for CueDuration {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CueDuration {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.frames().cmp(&other.frames())
+    }
+}
+
 impl CueDuration {
     pub fn zero() -> Self {
         Self {
@@ -67,6 +96,43 @@ impl CueDuration {
             frame: 0,
         }
     }
+
+    pub fn from_frames(frames: u32) -> Self {
+        Self {
+            min: frames / (60 * FRAMES_PER_SECOND),
+            sec: (frames / FRAMES_PER_SECOND) % 60,
+            frame: frames % FRAMES_PER_SECOND,
+        }
+    }
+
+    /// Duration of `samples` samples at `sample_rate`, truncated to a whole CD frame.
+    /// Zero if `sample_rate` is 0.
+    pub fn from_samples(samples: u64, sample_rate: u32) -> Self {
+        let frames = (samples * FRAMES_PER_SECOND as u64)
+            .checked_div(sample_rate as u64)
+            .unwrap_or(0);
+        Self::from_frames(frames as u32)
+    }
+
+    /// Total number of CD frames
+    pub fn frames(&self) -> u32 {
+        (self.min * 60 + self.sec) * FRAMES_PER_SECOND + self.frame
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.frames()
+            .checked_sub(rhs.frames())
+            .map(Self::from_frames)
+    }
+
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self::from_frames(self.frames().saturating_sub(rhs.frames()))
+    }
+
+    /// Number of samples at `sample_rate`
+    pub fn samples(&self, sample_rate: u32) -> u64 {
+        self.frames() as u64 * sample_rate as u64 / FRAMES_PER_SECOND as u64
+    }
 }
 
 impl Default for DurationFormat {
@@ -96,7 +162,7 @@ impl DurationFormat {
                 CueDuration {
                     min: *min,
                     sec: *sec,
-                    frame: frame,
+                    frame,
                 }
             }
         }
@@ -0,0 +1,343 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::{fmt::Display, ops::RangeInclusive};
+
+use crate::{cue_duration::CueDuration, cue_sheet::CueSheet, cue_track::CueTrack};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError {
+    NoTrack(u32),
+    /// A track cannot be inserted with this number
+    InvalidPosition(u32),
+    /// The position is not after INDEX 01 of the track and before the next track
+    OutsideTrack(u32),
+    /// The track is the last one, so it has no following track
+    NoNextTrack(u32),
+    /// The next track starts a new file, so the boundary cannot be dropped
+    SpansFiles(u32),
+    /// The first track of the range comes after the last one
+    InvalidRange(u32, u32),
+    /// The merged track would need an INDEX number above 99
+    TooManyIndexes(u32),
+    /// The sheet would have a track number above 99
+    TooManyTracks,
+    /// The track switches to another file after its first INDEX
+    TrackSpansFiles(u32),
+}
+
+impl Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditError::NoTrack(track) => write!(f, "no TRACK {:02}", track),
+            EditError::InvalidPosition(track) => {
+                write!(f, "cannot insert a track as TRACK {:02}", track)
+            }
+            EditError::OutsideTrack(track) => {
+                write!(f, "the position is outside TRACK {:02}", track)
+            }
+            EditError::NoNextTrack(track) => write!(f, "TRACK {:02} is the last track", track),
+            EditError::SpansFiles(track) => {
+                write!(f, "the track after TRACK {:02} starts a new file", track)
+            }
+            EditError::InvalidRange(first, last) => {
+                write!(f, "TRACK {:02} comes after TRACK {:02}", first, last)
+            }
+            EditError::TooManyIndexes(track) => {
+                write!(f, "TRACK {:02} would have more than 99 INDEX", track)
+            }
+            EditError::TooManyTracks => write!(f, "a sheet cannot go past TRACK 99"),
+            EditError::TrackSpansFiles(track) => {
+                write!(f, "TRACK {:02} spans several files", track)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// Metadata of the track created by [`CueSheet::split_track`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitMetadata {
+    /// Copy the CD-Text values and REM entries of the split track
+    Copy,
+    /// Leave the new track without CD-Text values and REM entries
+    Clear,
+}
+
+/// What [`CueSheet::merge_tracks`] does with the INDEX of the second track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeMode {
+    /// Keep them as the next INDEX of the first track, INDEX 02 and after
+    SubIndexes,
+    /// Drop them, the first track goes on until the end of the second one
+    DropBoundary,
+}
+
+impl CueSheet {
+    /// Number of the first track, from which the tracks are renumbered
+    fn first_number(&self) -> u32 {
+        self.tracks().next().map_or(1, CueTrack::number)
+    }
+
+    /// Place of the track `number` in the sheet
+    fn track_position(&self, number: u32) -> Result<usize, EditError> {
+        self.tracks()
+            .position(|track| track.number() == number)
+            .ok_or(EditError::NoTrack(number))
+    }
+
+    /// Apply `edit` to the tracks in order, then number them from the first number
+    fn edit_tracks<T, F: FnOnce(&mut Vec<CueTrack>) -> T>(&mut self, edit: F) -> T {
+        let first = self.first_number();
+        let mut tracks = std::mem::take(self.tracks_mut())
+            .into_iter()
+            .collect::<Vec<_>>();
+        let result = edit(&mut tracks);
+        for (i, track) in tracks.iter_mut().enumerate() {
+            let _ = track.set_number(first + i as u32);
+        }
+        *self.tracks_mut() = tracks.into_iter().collect();
+        result
+    }
+
+    /// Insert `track` as the track `number`, the following tracks are renumbered.
+    ///
+    /// `number` goes from the first track number to one past the last one. The
+    /// number of `track` itself is ignored. Fails if the last track would be numbered
+    /// above 99.
+    /// Duration-based INDEX values follow their track, so the positions written by
+    /// `repr(true)` are recomputed. Absolute positions are kept as they are.
+    pub fn insert_track(&mut self, number: u32, track: CueTrack) -> Result<&mut Self, EditError> {
+        let position = number
+            .checked_sub(self.first_number())
+            .map(|position| position as usize)
+            .filter(|position| *position <= self.tracks().count())
+            .ok_or(EditError::InvalidPosition(number))?;
+        if self.first_number() as usize + self.tracks().count() > 99 {
+            return Err(EditError::TooManyTracks);
+        }
+        self.edit_tracks(|tracks| tracks.insert(position, track));
+        Ok(self)
+    }
+
+    /// Remove the track `number` and renumber the following tracks
+    pub fn remove_track(&mut self, number: u32) -> Result<CueTrack, EditError> {
+        let position = self.track_position(number)?;
+        Ok(self.edit_tracks(|tracks| tracks.remove(position)))
+    }
+
+    /// Move the track `from` so that it becomes the track `to`, renumbering the tracks in between
+    pub fn move_track(&mut self, from: u32, to: u32) -> Result<&mut Self, EditError> {
+        let from = self.track_position(from)?;
+        let to = self.track_position(to)?;
+        self.edit_tracks(|tracks| {
+            let track = tracks.remove(from);
+            tracks.insert(to, track)
+        });
+        Ok(self)
+    }
+
+    /// Exchange the tracks `a` and `b`, each one taking the number of the other
+    pub fn swap_tracks(&mut self, a: u32, b: u32) -> Result<&mut Self, EditError> {
+        let a = self.track_position(a)?;
+        let b = self.track_position(b)?;
+        self.edit_tracks(|tracks| tracks.swap(a, b));
+        Ok(self)
+    }
+
+    /// Split the track `number` in two, the second track starting at `at`.
+    ///
+    /// The INDEX after `at` and the POSTGAP go to the new track, whose INDEX are
+    /// numbered from INDEX 01, and the following tracks are renumbered. An INDEX at
+    /// `at` itself is replaced by INDEX 01 of the new track. `at` must be after INDEX 01
+    /// of the track and before the start of the next track, and a track that switches
+    /// to another file after its first INDEX cannot be split.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn split_track(
+        &mut self,
+        number: u32,
+        at: CueDuration,
+        metadata: SplitMetadata,
+    ) -> Result<&mut Self, EditError> {
+        let position = self.track_position(number)?;
+        let tracks = self.tracks().collect::<Vec<_>>();
+        let track = tracks[position];
+        let first_index = track.indexes().keys().next();
+        if track
+            .index_files()
+            .keys()
+            .any(|index| Some(index) != first_index)
+        {
+            return Err(EditError::TrackSpansFiles(number));
+        }
+        if self.first_number() as usize + tracks.len() > 99 {
+            return Err(EditError::TooManyTracks);
+        }
+        let next_start = tracks
+            .get(position + 1)
+            .filter(|next| next.file().is_none())
+            .filter(|next| {
+                let first = next.indexes().keys().next();
+                first.is_none_or(|index| !next.index_files().contains_key(index))
+            })
+            .and_then(|next| next.first_position());
+        let inside = track.start().is_some_and(|start| start < at)
+            && next_start.is_none_or(|next_start| at < next_start);
+        if !inside {
+            return Err(EditError::OutsideTrack(number));
+        }
+
+        let mut second = track.clone();
+        let _ = second.set_file(None);
+        second.indexes_mut().clear();
+        second.index_files_mut().clear();
+        second.unknown_commands_mut().clear();
+        *second.pregap_mut() = None;
+        if metadata == SplitMetadata::Clear {
+            let _ = second.clear_metadata();
+        }
+        let mut first = track.clone();
+        *first.postgap_mut() = None;
+        let _ = second.indexes_mut().insert(1, at);
+        first.indexes_mut().retain(|_, position| *position != at);
+        let moved = track
+            .indexes()
+            .iter()
+            .filter(|(_, position)| **position > at)
+            .map(|(index, _)| *index)
+            .collect::<Vec<_>>();
+        for (new_index, index) in (2..).zip(moved) {
+            if let Some(position) = first.indexes_mut().remove(&index) {
+                let _ = second.indexes_mut().insert(new_index, position);
+            }
+            if let Some(file) = first.index_files_mut().remove(&index) {
+                let _ = second.index_files_mut().insert(new_index, file);
+            }
+        }
+
+        self.edit_tracks(|tracks| {
+            tracks[position] = first;
+            tracks.insert(position + 1, second)
+        });
+        Ok(self)
+    }
+
+    /// Merge the track `number` with the next one, which is removed.
+    ///
+    /// The merged track keeps the metadata of the first track and the POSTGAP of the
+    /// second one, or its own if the second has none. A PREGAP command of the second
+    /// track is dropped. The following tracks are renumbered.
+    /// With [`MergeMode::SubIndexes`], fails if the INDEX of the second track would be
+    /// numbered above 99.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn merge_tracks(&mut self, number: u32, mode: MergeMode) -> Result<&mut Self, EditError> {
+        let position = self.track_position(number)?;
+        let tracks = self.tracks().collect::<Vec<_>>();
+        let second = tracks
+            .get(position + 1)
+            .ok_or(EditError::NoNextTrack(number))?;
+        let mut merged = tracks[position].clone();
+        *merged.postgap_mut() = second.postgap().or(merged.postgap());
+        match mode {
+            MergeMode::DropBoundary => {
+                let spans_files = second.file().is_some() || !second.index_files().is_empty();
+                if spans_files {
+                    return Err(EditError::SpansFiles(number));
+                }
+            }
+            MergeMode::SubIndexes => {
+                let last = merged.indexes().keys().next_back().copied().unwrap_or(0);
+                if last as usize + second.indexes().len() > 99 {
+                    return Err(EditError::TooManyIndexes(number));
+                }
+                for (new_index, (index, position)) in (last + 1..).zip(second.indexes()) {
+                    let _ = merged.indexes_mut().insert(new_index, *position);
+                    let starts_file = second.indexes().keys().next() == Some(index);
+                    let file =
+                        second
+                            .index_files()
+                            .get(index)
+                            .cloned()
+                            .or_else(|| match starts_file {
+                                true => second
+                                    .file()
+                                    .map(|(name, format)| (name.to_string(), format)),
+                                false => None,
+                            });
+                    if let Some(file) = file {
+                        let _ = merged.index_files_mut().insert(new_index, file);
+                    }
+                }
+            }
+        }
+
+        self.edit_tracks(|tracks| {
+            tracks[position] = merged;
+            let _ = tracks.remove(position + 1);
+        });
+        Ok(self)
+    }
+
+    /// New sheet made of the tracks in `range`, such as `5..=9`.
+    ///
+    /// The tracks are numbered from 1 and the disc metadata is kept. The FILE of the
+    /// sheet is the one of the first extracted INDEX, and positions in that file are
+    /// moved so that the first extracted track starts at 00:00:00, as in a trimmed file.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn extract_tracks(&self, range: RangeInclusive<u32>) -> Result<CueSheet, EditError> {
+        let (first, last) = (*range.start(), *range.end());
+        if first > last {
+            return Err(EditError::InvalidRange(first, last));
+        }
+        let _ = self.track_position(first)?;
+        let _ = self.track_position(last)?;
+
+        let entries = self
+            .index_entries(false)
+            .into_iter()
+            .filter(|entry| range.contains(&entry.track))
+            .collect::<Vec<_>>();
+        let files = self.files();
+        let (file, offset) = entries.first().map_or((0, CueDuration::zero()), |entry| {
+            (entry.file, entry.position)
+        });
+        let (name, format) = files[file];
+
+        let mut sheet = self.clone();
+        let _ = sheet.set_file(name, format);
+        let tracks = std::mem::take(sheet.tracks_mut());
+        *sheet.tracks_mut() = tracks
+            .into_iter()
+            .filter(|track| range.contains(&track.number()))
+            .enumerate()
+            .map(|(i, mut track)| {
+                let number = track.number();
+                let _ = track.set_number(i as u32 + 1);
+                if i == 0 {
+                    let _ = track.set_file(None);
+                    if let Some(index) = track.indexes().keys().next().copied() {
+                        let _ = track.index_files_mut().remove(&index);
+                    }
+                }
+                for (index, position) in track.indexes_mut().iter_mut() {
+                    let in_first_file = entries.iter().any(|entry| {
+                        (entry.track, entry.index, entry.file) == (number, *index, file)
+                    });
+                    if in_first_file {
+                        *position = position.saturating_sub(offset);
+                    }
+                }
+                track
+            })
+            .collect();
+        Ok(sheet)
+    }
+}
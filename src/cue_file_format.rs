@@ -31,3 +31,19 @@ impl Display for CueFileFormat {
         write!(f, "{}", s)
     }
 }
+
+impl CueFileFormat {
+    /// Guess the FILE type from an audio file extension.
+    ///
+    /// Lossless formats that are decoded to PCM by players (flac, ape, wv, ...)
+    /// are conventionally written as `WAVE`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "wav" | "flac" | "ape" | "wv" | "tta" | "ogg" | "opus" | "m4a" => Some(Self::WAVE),
+            "aif" | "aiff" | "aifc" => Some(Self::AIFF),
+            "mp3" => Some(Self::MP3),
+            "bin" | "raw" => Some(Self::BINARY),
+            _ => None,
+        }
+    }
+}
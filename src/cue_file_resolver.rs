@@ -0,0 +1,203 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Extensions tried, in order, when the FILE entry points to a file that
+/// was transcoded after the sheet was written.
+const AUDIO_EXTENSIONS: [&str; 13] = [
+    "wav", "flac", "ape", "wv", "tta", "aiff", "aif", "mp3", "m4a", "ogg", "opus", "bin", "raw",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileMatch {
+    /// The FILE entry names the file exactly
+    Exact,
+    /// The file was found with a different letter case
+    CaseInsensitive,
+    /// The file was found with the same name but another audio extension
+    Transcoded,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileResolution {
+    /// Path of the file on disk
+    pub path: PathBuf,
+    /// Path of the file relative to the sheet directory, with `/` separators
+    pub relative: String,
+    pub matched: FileMatch,
+}
+
+#[derive(Debug)]
+pub enum ResolveError {
+    /// The FILE entry is an absolute path
+    Absolute(String),
+    /// The FILE entry points outside of the sheet directory
+    Escapes(String),
+    NotFound(String),
+    Io(std::io::Error),
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveError::Absolute(name) => write!(f, "\"{}\" is an absolute path", name),
+            ResolveError::Escapes(name) => {
+                write!(f, "\"{}\" points outside of the sheet directory", name)
+            }
+            ResolveError::NotFound(name) => write!(f, "\"{}\" not found", name),
+            ResolveError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResolveError {}
+
+impl From<std::io::Error> for ResolveError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+/// Split a FILE name into its components, accepting both `/` and `\` separators.
+fn components(name: &str) -> Result<Vec<&str>, ResolveError> {
+    let bytes = name.as_bytes();
+    let is_drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    if is_drive || name.starts_with('/') || name.starts_with('\\') {
+        return Err(ResolveError::Absolute(name.to_string()));
+    }
+    let mut parts = Vec::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => continue,
+            ".." => {
+                if parts.pop().is_none() {
+                    return Err(ResolveError::Escapes(name.to_string()));
+                }
+            }
+            part => parts.push(part),
+        }
+    }
+    match parts.is_empty() {
+        true => Err(ResolveError::NotFound(name.to_string())),
+        false => Ok(parts),
+    }
+}
+
+fn entries(dir: &Path) -> Result<Vec<(String, PathBuf)>, ResolveError> {
+    let mut entries = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            Some((name, entry.path()))
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    Ok(entries)
+}
+
+fn split_extension(name: &str) -> (&str, Option<&str>) {
+    match name.rfind('.') {
+        Some(0) | None => (name, None),
+        Some(i) => (&name[..i], Some(&name[i + 1..])),
+    }
+}
+
+/// Find `part` in `dir`, first exactly then ignoring the letter case.
+fn find_entry(
+    dir: &Path,
+    part: &str,
+    is_dir: bool,
+) -> Result<Option<(String, FileMatch)>, ResolveError> {
+    let exact = dir.join(part);
+    if exact.exists() && exact.is_dir() == is_dir {
+        return Ok(Some((part.to_string(), FileMatch::Exact)));
+    }
+    let lower = part.to_lowercase();
+    let found = entries(dir)?
+        .into_iter()
+        .find(|(name, path)| name.to_lowercase() == lower && path.is_dir() == is_dir)
+        .map(|(name, _)| (name, FileMatch::CaseInsensitive));
+    Ok(found)
+}
+
+/// Find an audio file in `dir` with the same stem as `part` but another extension.
+fn find_transcoded(dir: &Path, part: &str) -> Result<Option<String>, ResolveError> {
+    let stem = split_extension(part).0.to_lowercase();
+    let candidates = entries(dir)?
+        .into_iter()
+        .filter(|(_, path)| path.is_file())
+        .filter_map(|(name, _)| {
+            let (entry_stem, extension) = split_extension(&name);
+            let extension = extension?.to_ascii_lowercase();
+            let rank = AUDIO_EXTENSIONS.iter().position(|e| *e == extension)?;
+            (entry_stem.to_lowercase() == stem).then_some((rank, name))
+        })
+        .min();
+    Ok(candidates.map(|(_, name)| name))
+}
+
+/// Resolve the FILE entry `name` relative to `sheet_dir`.
+///
+/// Paths that are absolute or that leave `sheet_dir`, including through
+/// symbolic links, are refused.
+pub(crate) fn resolve(sheet_dir: &Path, name: &str) -> Result<FileResolution, ResolveError> {
+    let parts = components(name)?;
+    let (last, dirs) = parts.split_last().expect("components are never empty");
+    let not_found = || ResolveError::NotFound(name.to_string());
+
+    let mut current = sheet_dir.to_path_buf();
+    let mut relative = Vec::with_capacity(parts.len());
+    let mut matched = FileMatch::Exact;
+    for part in dirs {
+        let (found, kind) = find_entry(&current, part, true)?.ok_or_else(not_found)?;
+        if kind != FileMatch::Exact {
+            matched = kind;
+        }
+        current.push(&found);
+        relative.push(found);
+    }
+
+    let (found, kind) = match find_entry(&current, last, false)? {
+        Some(found) => found,
+        None => find_transcoded(&current, last)?
+            .map(|found| (found, FileMatch::Transcoded))
+            .ok_or_else(not_found)?,
+    };
+    if kind != FileMatch::Exact {
+        matched = kind;
+    }
+    current.push(&found);
+    relative.push(found);
+
+    let root = sheet_dir.canonicalize()?;
+    let path = current.canonicalize()?;
+    if !path.starts_with(&root) {
+        return Err(ResolveError::Escapes(name.to_string()));
+    }
+
+    Ok(FileResolution {
+        path,
+        relative: relative.join("/"),
+        matched,
+    })
+}
+
+/// Directory containing the sheet at `cue_path`.
+pub(crate) fn sheet_directory(cue_path: &Path) -> &Path {
+    match cue_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
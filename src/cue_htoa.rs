@@ -0,0 +1,61 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use crate::{
+    cue_duration::{CueDuration, DurationFormat},
+    cue_sheet::CueSheet,
+    cue_track::CueTrack,
+};
+
+impl CueSheet {
+    /// Length of the hidden track one audio (HTOA), the audio stored before INDEX 01 of
+    /// track 1 when its INDEX 00 is at 00:00:00.
+    ///
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn htoa(&self) -> Option<CueDuration> {
+        let first = self.tracks().next().filter(|track| track.number() == 1)?;
+        let index00 = first.indexes().get(&0)?;
+        let index01 = first.indexes().get(&1)?;
+        let in_first_file = first.index_files().get(&1).is_none();
+        (*index00 == CueDuration::zero() && *index01 > *index00 && in_first_file)
+            .then_some(*index01)
+    }
+
+    /// The hidden track one audio as a virtual track 0, for splitting and playlists.
+    ///
+    /// It starts at 00:00:00 of the first file and ends at INDEX 01 of track 1.
+    pub fn htoa_track(&self) -> Option<CueTrack> {
+        let _ = self.htoa()?;
+        let first = self.tracks().next()?;
+        let mut track = CueTrack::new(0, first.mode());
+        let _ = track.add_index(1, DurationFormat::MinSec(0, 0));
+        Some(track)
+    }
+
+    /// Keep `duration` of hidden track one audio before track 1, for sheets built from
+    /// durations and written with `sum`.
+    ///
+    /// The length is stored as INDEX 00 of track 1, which is written at 00:00:00 and
+    /// followed by INDEX 01 at the end of the hidden audio. Add the tracks first: the
+    /// sheet is left unchanged when it has no track 1.
+    pub fn add_htoa(&mut self, duration: DurationFormat) -> &mut Self {
+        let tracks = std::mem::take(self.tracks_mut());
+        *self.tracks_mut() = tracks
+            .into_iter()
+            .map(|mut track| {
+                if track.number() == 1 {
+                    let _ = track.add_index(0, duration);
+                }
+                track
+            })
+            .collect();
+        self
+    }
+}
@@ -0,0 +1,243 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cue_audio_info::{AudioInfo, ProbeError},
+    cue_duration::CueDuration,
+    cue_file_format::CueFileFormat,
+    cue_pcm,
+    cue_sheet::CueSheet,
+    cue_track::CueTrack,
+    cue_track_mode::CueTrackMode,
+};
+
+#[derive(Debug)]
+pub enum JoinError {
+    Probe(ProbeError),
+    Io(std::io::Error),
+    NoInput,
+    /// The file does not hold uncompressed integer PCM samples
+    NotPcm(PathBuf),
+    /// The sample format of the file differs from the one of the first file
+    FormatMismatch(PathBuf),
+    /// The output file is one of the files to join
+    OutputIsInput(PathBuf),
+}
+
+impl Display for JoinError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinError::Probe(e) => write!(f, "{}", e),
+            JoinError::Io(e) => write!(f, "{}", e),
+            JoinError::NoInput => write!(f, "no file to join"),
+            JoinError::NotPcm(path) => write!(f, "{} is not uncompressed PCM", path.display()),
+            JoinError::FormatMismatch(path) => {
+                write!(f, "{} has a different sample format", path.display())
+            }
+            JoinError::OutputIsInput(path) => {
+                write!(f, "{} is both an input and the output", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+impl From<ProbeError> for JoinError {
+    fn from(value: ProbeError) -> Self {
+        Self::Probe(value)
+    }
+}
+
+impl From<std::io::Error> for JoinError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinWarning {
+    /// The file of the track is not a whole number of CD frames long, so the
+    /// following INDEX 01 is rounded down to a CD frame
+    NotFrameAligned { track: u32, extra_samples: u64 },
+}
+
+impl Display for JoinWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JoinWarning::NotFrameAligned {
+                track,
+                extra_samples,
+            } => write!(
+                f,
+                "TRACK {:02} is not a whole number of CD frames ({} extra samples)",
+                track, extra_samples
+            ),
+        }
+    }
+}
+
+/// Read the LIST INFO chunk of a WAVE file, keyed by INFO identifiers such as `INAM`.
+fn read_wave_info(path: &Path) -> Result<BTreeMap<String, String>, std::io::Error> {
+    let mut tags = BTreeMap::new();
+    let file = File::open(path)?;
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Ok(tags);
+    }
+    let mut chunk = [0u8; 8];
+    while reader.read_exact(&mut chunk).is_ok() {
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+        let start = reader.stream_position()?;
+        if &chunk[0..4] == b"LIST" {
+            // the size is not trusted past the end of the file
+            let mut list = vec![0u8; size.min(file_len.saturating_sub(start)) as usize];
+            reader.read_exact(&mut list)?;
+            if list.starts_with(b"INFO") {
+                let mut offset = 4;
+                while offset + 8 <= list.len() {
+                    let id = String::from_utf8_lossy(&list[offset..offset + 4]).to_string();
+                    let len = u32::from_le_bytes([
+                        list[offset + 4],
+                        list[offset + 5],
+                        list[offset + 6],
+                        list[offset + 7],
+                    ]) as usize;
+                    let value = list.get(offset + 8..offset + 8 + len).unwrap_or_default();
+                    let value = String::from_utf8_lossy(value);
+                    let _ = tags.insert(id, value.trim_end_matches('\0').to_string());
+                    offset += 8 + len + (len & 1);
+                }
+            }
+        }
+        reader.seek(SeekFrom::Start(start + size + (size & 1)))?;
+    }
+    Ok(tags)
+}
+
+impl CueSheet {
+    /// Concatenate per-track files into the WAVE image `output` and build its sheet.
+    ///
+    /// Every file must hold PCM samples with the same sample format. INDEX 01 of each
+    /// track is at the first sample of its file. With `with_tags`, titles and performers
+    /// are read from the LIST INFO chunk of WAVE files. `output` can not be one of `inputs`.
+    pub fn join_audio<P: AsRef<Path>, Q: AsRef<Path>>(
+        inputs: &[P],
+        output: Q,
+        with_tags: bool,
+    ) -> Result<(CueSheet, Vec<JoinWarning>), JoinError> {
+        let first = inputs.first().ok_or(JoinError::NoInput)?;
+        let format = AudioInfo::probe(first, CueFileFormat::WAVE)?;
+        let mut infos = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            let path = input.as_ref();
+            let info = AudioInfo::probe(path, CueFileFormat::WAVE)?;
+            let pcm = info
+                .pcm
+                .ok_or_else(|| JoinError::NotPcm(path.to_path_buf()))?;
+            let same_format = (info.sample_rate, info.channels, info.bits_per_sample)
+                == (format.sample_rate, format.channels, format.bits_per_sample);
+            if !same_format {
+                return Err(JoinError::FormatMismatch(path.to_path_buf()));
+            }
+            infos.push((path, info, pcm));
+        }
+
+        let output = output.as_ref();
+        // An existing output is truncated, which must not happen to an input
+        if let Ok(canonical) = output.canonicalize() {
+            for (path, _, _) in &infos {
+                if path.canonicalize().is_ok_and(|path| path == canonical) {
+                    return Err(JoinError::OutputIsInput(path.to_path_buf()));
+                }
+            }
+        }
+        let total_samples = infos
+            .iter()
+            .map(|(_, info, _)| info.total_samples)
+            .sum::<u64>();
+        let header = cue_pcm::wav_header(
+            format.channels,
+            format.sample_rate,
+            format.bits_per_sample,
+            total_samples * format.block_align(),
+        )?;
+        let mut writer = BufWriter::new(File::create(output)?);
+        writer.write_all(&header)?;
+
+        let filename = output
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut sheet = CueSheet::new(&filename, CueFileFormat::WAVE);
+        let mut warnings = Vec::new();
+        let mut album = Vec::new();
+        let mut start = 0u64;
+        for (i, (path, info, pcm)) in infos.iter().enumerate() {
+            let number = i as u32 + 1;
+            let mut reader = BufReader::new(File::open(path)?);
+            cue_pcm::copy_samples(&mut reader, &mut writer, info, *pcm, 0, info.total_samples)?;
+
+            let position = CueDuration::from_samples(start, format.sample_rate);
+            let mut track = CueTrack::new(number, CueTrackMode::AUDIO);
+            let _ = track.indexes_mut().insert(1, position);
+
+            let extra_samples = info.total_samples - info.duration().samples(format.sample_rate);
+            if extra_samples != 0 {
+                warnings.push(JoinWarning::NotFrameAligned {
+                    track: number,
+                    extra_samples,
+                })
+            }
+
+            if with_tags {
+                let tags = read_wave_info(path)?;
+                if let Some(title) = tags.get("INAM") {
+                    let _ = track.add_title(title);
+                }
+                if let Some(performer) = tags.get("IART") {
+                    let _ = track.add_performer(performer);
+                }
+                album.push((tags.get("IPRD").cloned(), tags.get("IART").cloned()));
+            }
+            let _ = sheet.add_track(track);
+            start += info.total_samples;
+        }
+        writer.flush()?;
+
+        // Disc metadata is only filled when every file agrees on it
+        let shared = |values: Vec<Option<String>>| {
+            let first = values.first().cloned().flatten()?;
+            values
+                .iter()
+                .all(|value| value.as_ref() == Some(&first))
+                .then_some(first)
+        };
+        let (albums, artists): (Vec<_>, Vec<_>) = album.into_iter().unzip();
+        if let Some(title) = shared(albums) {
+            let _ = sheet.add_title(&title);
+        }
+        if let Some(performer) = shared(artists) {
+            let _ = sheet.add_performer(&performer);
+        }
+        Ok((sheet, warnings))
+    }
+}
@@ -0,0 +1,170 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::fmt::Display;
+
+use crate::{
+    cue_duration::CueDuration, cue_file_format::CueFileFormat, cue_sheet::CueSheet,
+    cue_track::CueTrack,
+};
+
+/// Where the pregap of each track is stored in a file-per-track layout, as produced by EAC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapLayout {
+    /// The pregap is at the end of the previous track file
+    Appended,
+    /// The pregap is at the start of the track file
+    Prepended,
+    /// The pregap is not stored and a PREGAP command stands for it
+    LeftOut,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// The sheet references more than one file
+    NotSingleImage,
+    /// The length of this file is needed to join the files
+    MissingFileLength(String),
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::NotSingleImage => write!(f, "the sheet references more than one file"),
+            LayoutError::MissingFileLength(name) => write!(f, "unknown length for \"{}\"", name),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl CueSheet {
+    /// Convert a single-image sheet into a sheet with one file per track.
+    ///
+    /// `file_name` gives the name of the file of each track. The pregap of the first
+    /// track always stays at the start of its file, except with [`GapLayout::LeftOut`].
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn to_file_per_track<F: Fn(&CueTrack) -> String>(
+        &self,
+        layout: GapLayout,
+        file_name: F,
+    ) -> Result<CueSheet, LayoutError> {
+        if self.files().len() > 1 {
+            return Err(LayoutError::NotSingleImage);
+        }
+        let format = self.file_format();
+        let tracks = self.tracks().collect::<Vec<_>>();
+        if tracks.is_empty() {
+            return Ok(self.clone());
+        }
+        let names = tracks
+            .iter()
+            .map(|track| file_name(track))
+            .collect::<Vec<_>>();
+        let zero = CueDuration::zero();
+        let starts = tracks
+            .iter()
+            .map(|track| track.start().unwrap_or(zero))
+            .collect::<Vec<_>>();
+        let firsts = tracks
+            .iter()
+            .map(|track| track.first_position().unwrap_or(zero))
+            .collect::<Vec<_>>();
+        let file_starts = (0..tracks.len())
+            .map(|i| match (layout, i) {
+                (GapLayout::Prepended, _) | (GapLayout::Appended, 0) => firsts[i],
+                (GapLayout::Appended, _) | (GapLayout::LeftOut, _) => starts[i],
+            })
+            .collect::<Vec<_>>();
+
+        let mut sheet = self.clone();
+        let _ = sheet.set_file(&names[0], format);
+        *sheet.tracks_mut() = tracks
+            .iter()
+            .enumerate()
+            .map(|(i, track)| {
+                let mut track = (*track).clone();
+                let gap = starts[i].saturating_sub(firsts[i]);
+                let _ = track.set_file(None);
+                track.index_files_mut().clear();
+                let gap_in_previous = layout == GapLayout::Appended && i > 0;
+                let has_gap = track.indexes().contains_key(&0);
+                if layout == GapLayout::LeftOut && has_gap {
+                    let _ = track.indexes_mut().remove(&0);
+                    let pregap = track.pregap_mut();
+                    *pregap = Some(pregap.unwrap_or(zero) + gap);
+                }
+                for (index, position) in track.indexes_mut().iter_mut() {
+                    let file_start = match *index == 0 && gap_in_previous {
+                        true => file_starts[i - 1],
+                        false => file_starts[i],
+                    };
+                    *position = position.saturating_sub(file_start);
+                }
+                match (gap_in_previous && has_gap, i) {
+                    (true, _) => {
+                        let _ = track.add_index_file(1, &names[i], format);
+                    }
+                    (false, 0) => (),
+                    (false, _) => {
+                        let _ = track.set_file(Some((names[i].clone(), format)));
+                    }
+                }
+                track
+            })
+            .collect();
+        Ok(sheet)
+    }
+
+    /// Convert a sheet with several files into a single-image sheet named `filename`.
+    ///
+    /// `file_lengths` holds the length of the files of [`CueSheet::files`], all of them
+    /// but the last one are needed. PREGAP commands are kept as they are, use
+    /// [`CueSheet::pregaps_to_indexes`] if the silence is added to the image.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn to_single_image(
+        &self,
+        filename: &str,
+        format: CueFileFormat,
+        file_lengths: &[CueDuration],
+    ) -> Result<CueSheet, LayoutError> {
+        let files = self.files();
+        let mut offsets = vec![CueDuration::zero()];
+        for (i, (name, _)) in files.iter().enumerate().take(files.len() - 1) {
+            let length = file_lengths
+                .get(i)
+                .ok_or_else(|| LayoutError::MissingFileLength(name.to_string()))?;
+            offsets.push(offsets[i] + *length);
+        }
+
+        let entries = self.index_entries(false);
+        let mut sheet = self.clone();
+        let _ = sheet.set_file(filename, format);
+        let tracks = std::mem::take(sheet.tracks_mut());
+        *sheet.tracks_mut() = tracks
+            .into_iter()
+            .map(|mut track| {
+                let number = track.number();
+                let _ = track.set_file(None);
+                track.index_files_mut().clear();
+                for (index, position) in track.indexes_mut().iter_mut() {
+                    let entry = entries
+                        .iter()
+                        .find(|entry| entry.track == number && entry.index == *index);
+                    if let Some(entry) = entry {
+                        *position = offsets[entry.file] + *position;
+                    }
+                }
+                track
+            })
+            .collect();
+        Ok(sheet)
+    }
+}
@@ -0,0 +1,135 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use crate::{cue_sheet::CueSheet, cue_track::CueTrack};
+
+/// Keys that only describe the track they are written in. At disc level, TITLE
+/// is the album title, and ISRC and MESSAGE are specific to one track.
+pub(crate) const TRACK_ONLY_KEYS: [&str; 4] = ["TITLE", "ISCR", "ISRC", "MESSAGE"];
+
+/// Where an effective value comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataSource {
+    Track,
+    /// The track has no value and inherits the one of the sheet
+    Sheet,
+}
+
+/// Metadata of a track, completed by the values of its sheet.
+///
+/// A key is looked up in the CD-Text of the track, then in its REM entries. If the
+/// track has neither, the CD-Text then the REM entries of the sheet are used, except
+/// for TITLE, ISRC and MESSAGE which are never inherited.
+#[derive(Debug, Clone, Copy)]
+pub struct TrackMetadata<'a> {
+    sheet: &'a CueSheet,
+    track: &'a CueTrack,
+}
+
+impl<'a> TrackMetadata<'a> {
+    /// Effective value of `key` and where it comes from
+    pub fn get_with_source(&self, key: &str) -> Option<(&'a str, MetadataSource)> {
+        let key = key.to_ascii_uppercase();
+        let track = self.track.cd_text(&key).or(self.track.rem(&key));
+        if let Some(value) = track {
+            return Some((value, MetadataSource::Track));
+        }
+        match TRACK_ONLY_KEYS.contains(&key.as_str()) {
+            true => None,
+            false => self
+                .sheet
+                .cd_text(&key)
+                .or(self.sheet.rem(&key))
+                .map(|value| (value, MetadataSource::Sheet)),
+        }
+    }
+
+    /// Effective value of the CD-Text or REM `key`
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.get_with_source(key).map(|(value, _)| value)
+    }
+
+    pub fn track(&self) -> &'a CueTrack {
+        self.track
+    }
+
+    pub fn title(&self) -> Option<&'a str> {
+        self.get("TITLE")
+    }
+
+    /// Artist of the track, the performer of the sheet if the track has none
+    pub fn performer(&self) -> Option<&'a str> {
+        self.get("PERFORMER")
+    }
+
+    pub fn songwriter(&self) -> Option<&'a str> {
+        self.get("SONGWRITER")
+    }
+
+    pub fn composer(&self) -> Option<&'a str> {
+        self.get("COMPOSER")
+    }
+
+    pub fn genre(&self) -> Option<&'a str> {
+        self.get("GENRE")
+    }
+
+    pub fn date(&self) -> Option<&'a str> {
+        self.get("DATE")
+    }
+
+    /// Title of the sheet
+    pub fn album(&self) -> Option<&'a str> {
+        self.sheet.cd_text("TITLE")
+    }
+
+    /// See [`CueSheet::album_performer`]
+    pub fn album_performer(&self) -> Option<&'a str> {
+        self.sheet.album_performer()
+    }
+}
+
+impl CueSheet {
+    /// Effective metadata of `track`, inheriting the values of the sheet
+    pub fn metadata<'a>(&'a self, track: &'a CueTrack) -> TrackMetadata<'a> {
+        TrackMetadata { sheet: self, track }
+    }
+
+    /// Effective metadata of every track
+    pub fn tracks_metadata(&self) -> impl Iterator<Item = TrackMetadata<'_>> {
+        self.tracks().map(|track| self.metadata(track))
+    }
+
+    /// Whether the tracks do not all have the same effective performer
+    pub fn is_compilation(&self) -> bool {
+        let mut performers = self.tracks_metadata().map(|track| track.performer());
+        match performers.next() {
+            None => false,
+            Some(first) => performers.any(|performer| performer != first),
+        }
+    }
+
+    /// Album artist of the sheet.
+    ///
+    /// This is the PERFORMER of the sheet, or the performer shared by every track
+    /// when the sheet has none. A compilation without a disc performer has no album artist.
+    pub fn album_performer(&self) -> Option<&str> {
+        if let Some(performer) = self.cd_text("PERFORMER") {
+            return Some(performer);
+        }
+        match self.is_compilation() {
+            true => None,
+            false => self
+                .tracks_metadata()
+                .next()
+                .and_then(|track| track.performer()),
+        }
+    }
+}
@@ -0,0 +1,1056 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
+
+use crate::{
+    cue_cd_text::{set_cd_text, CueCdText},
+    cue_diff::{CueChange, CueDiff, DiffScope},
+    cue_duration::{CueDuration, FRAMES_PER_SECOND},
+    cue_file_format::CueFileFormat,
+    cue_rem::RemEntry,
+    cue_sheet::CueSheet,
+    cue_track::CueTrack,
+    cue_track_flag::CueTrackFlag,
+    cue_track_mode::CueTrackMode,
+};
+
+const MODES: [CueTrackMode; 8] = [
+    CueTrackMode::AUDIO,
+    CueTrackMode::CDG,
+    CueTrackMode::MODE1_2048,
+    CueTrackMode::MODE1_2352,
+    CueTrackMode::MODE2_2336,
+    CueTrackMode::MODE2_2352,
+    CueTrackMode::CDI2336,
+    CueTrackMode::CDI2352,
+];
+
+const FORMATS: [CueFileFormat; 5] = [
+    CueFileFormat::BINARY,
+    CueFileFormat::MOTOROLA,
+    CueFileFormat::AIFF,
+    CueFileFormat::WAVE,
+    CueFileFormat::MP3,
+];
+
+const FLAGS: [CueTrackFlag; 4] = [
+    CueTrackFlag::PRE,
+    CueTrackFlag::DCP,
+    CueTrackFlag::F4CH,
+    CueTrackFlag::SCMS,
+];
+
+/// Value of a scope set by a patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchField {
+    Catalog,
+    /// FILE of the sheet, or FILE written before a TRACK
+    File,
+    /// FILE written before this INDEX inside a track
+    IndexFile(u32),
+    Mode,
+    CdText(String),
+    /// Values of every REM entry with this key
+    Rem(String),
+    /// Text of every REM line without value
+    BareRems,
+    Flags,
+    Pregap,
+    Postgap,
+    /// Position of the INDEX
+    Index(u32),
+}
+
+impl Display for PatchField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchField::Catalog => write!(f, "CATALOG"),
+            PatchField::File => write!(f, "FILE"),
+            PatchField::IndexFile(index) => write!(f, "FILE INDEX {:02}", index),
+            PatchField::Mode => write!(f, "MODE"),
+            PatchField::CdText(key) => write!(f, "CDTEXT {}", key),
+            PatchField::Rem(key) => write!(f, "REM {}", key),
+            PatchField::BareRems => write!(f, "BARE REM"),
+            PatchField::Flags => write!(f, "FLAGS"),
+            PatchField::Pregap => write!(f, "PREGAP"),
+            PatchField::Postgap => write!(f, "POSTGAP"),
+            PatchField::Index(index) => write!(f, "INDEX {:02}", index),
+        }
+    }
+}
+
+/// Value of a [`PatchField`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchValue {
+    /// The field is not set, or the track does not exist
+    None,
+    Text(String),
+    File(String, CueFileFormat),
+    Mode(CueTrackMode),
+    List(Vec<String>),
+    Flags(Vec<CueTrackFlag>),
+    Duration(CueDuration),
+}
+
+impl PatchValue {
+    fn text(value: Option<String>) -> Self {
+        value.map_or(PatchValue::None, PatchValue::Text)
+    }
+
+    fn file(file: Option<(String, CueFileFormat)>) -> Self {
+        file.map_or(PatchValue::None, |(name, format)| {
+            PatchValue::File(name, format)
+        })
+    }
+
+    fn duration(duration: Option<CueDuration>) -> Self {
+        duration.map_or(PatchValue::None, PatchValue::Duration)
+    }
+
+    fn as_duration(&self) -> Option<CueDuration> {
+        match self {
+            PatchValue::Duration(duration) => Some(*duration),
+            _ => None,
+        }
+    }
+}
+
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => quoted.extend(['\\', c]),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Display for PatchValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchValue::None => write!(f, "-"),
+            PatchValue::Text(text) => write!(f, "{}", quote(text)),
+            PatchValue::File(name, format) => write!(f, "{} {}", quote(name), format),
+            PatchValue::Mode(mode) => write!(f, "{}", mode),
+            PatchValue::List(values) => {
+                write!(f, "[")?;
+                for value in values {
+                    write!(f, " {}", quote(value))?;
+                }
+                write!(f, " ]")
+            }
+            PatchValue::Flags(flags) => {
+                write!(f, "[")?;
+                for flag in flags {
+                    write!(f, " {}", flag)?;
+                }
+                write!(f, " ]")
+            }
+            PatchValue::Duration(duration) => write!(f, "{}", duration),
+        }
+    }
+}
+
+/// One operation of a [`CuePatch`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchOp {
+    /// Add an empty track, a conflict if the track exists
+    AddTrack { track: u32, mode: CueTrackMode },
+    /// Remove the track if it exists
+    RemoveTrack(u32),
+    /// Give the track `from` the number `to`, a conflict if `from` does not exist or
+    /// `to` does
+    RenumberTrack { from: u32, to: u32 },
+    /// Replace `old` with `new`, a conflict if the current value is neither of them.
+    /// Removing a FILE that the track does not have, the FILE of the sheet or INDEX 01
+    /// of a track is a conflict too.
+    Set {
+        scope: DiffScope,
+        field: PatchField,
+        old: PatchValue,
+        new: PatchValue,
+    },
+    /// Move an INDEX from `old` by `delta` frames, a conflict if it is neither at `old`
+    /// nor already moved, or would move before the start of its file
+    ShiftIndex {
+        track: u32,
+        index: u32,
+        old: CueDuration,
+        delta: i64,
+    },
+}
+
+impl Display for PatchOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchOp::AddTrack { track, mode } => write!(f, "ADD TRACK {:02} {}", track, mode),
+            PatchOp::RemoveTrack(track) => write!(f, "REMOVE TRACK {:02}", track),
+            PatchOp::RenumberTrack { from, to } => {
+                write!(f, "RENUMBER TRACK {:02} -> {:02}", from, to)
+            }
+            PatchOp::Set {
+                scope,
+                field,
+                old,
+                new,
+            } => write!(f, "SET {} {} {} -> {}", scope, field, old, new),
+            PatchOp::ShiftIndex {
+                track,
+                index,
+                old,
+                delta,
+            } => write!(
+                f,
+                "SHIFT TRACK {:02} INDEX {:02} {} {:+}",
+                track, index, old, delta
+            ),
+        }
+    }
+}
+
+impl From<&CueChange> for PatchOp {
+    fn from(change: &CueChange) -> Self {
+        let set = |scope, field, old, new| PatchOp::Set {
+            scope,
+            field,
+            old,
+            new,
+        };
+        match change.clone() {
+            CueChange::TrackAdded { track, mode } => PatchOp::AddTrack { track, mode },
+            CueChange::TrackRemoved(track) => PatchOp::RemoveTrack(track),
+            CueChange::TrackRenumbered { old, new } => {
+                PatchOp::RenumberTrack { from: old, to: new }
+            }
+            CueChange::CatalogChanged { old, new } => set(
+                DiffScope::Sheet,
+                PatchField::Catalog,
+                PatchValue::text(old),
+                PatchValue::text(new),
+            ),
+            CueChange::FileChanged { scope, old, new } => set(
+                scope,
+                PatchField::File,
+                PatchValue::file(old),
+                PatchValue::file(new),
+            ),
+            CueChange::IndexFileChanged {
+                track,
+                index,
+                old,
+                new,
+            } => set(
+                DiffScope::Track(track),
+                PatchField::IndexFile(index),
+                PatchValue::file(old),
+                PatchValue::file(new),
+            ),
+            CueChange::ModeChanged { track, old, new } => set(
+                DiffScope::Track(track),
+                PatchField::Mode,
+                PatchValue::Mode(old),
+                PatchValue::Mode(new),
+            ),
+            CueChange::CdTextChanged {
+                scope,
+                key,
+                old,
+                new,
+            } => set(
+                scope,
+                PatchField::CdText(key),
+                PatchValue::text(old),
+                PatchValue::text(new),
+            ),
+            CueChange::RemChanged {
+                scope,
+                key,
+                old,
+                new,
+            } => set(
+                scope,
+                PatchField::Rem(key),
+                PatchValue::List(old),
+                PatchValue::List(new),
+            ),
+            CueChange::BareRemsChanged { scope, old, new } => set(
+                scope,
+                PatchField::BareRems,
+                PatchValue::List(old),
+                PatchValue::List(new),
+            ),
+            CueChange::FlagsChanged { track, old, new } => set(
+                DiffScope::Track(track),
+                PatchField::Flags,
+                PatchValue::Flags(old),
+                PatchValue::Flags(new),
+            ),
+            CueChange::PregapChanged { track, old, new } => set(
+                DiffScope::Track(track),
+                PatchField::Pregap,
+                PatchValue::duration(old),
+                PatchValue::duration(new),
+            ),
+            CueChange::PostgapChanged { track, old, new } => set(
+                DiffScope::Track(track),
+                PatchField::Postgap,
+                PatchValue::duration(old),
+                PatchValue::duration(new),
+            ),
+            CueChange::IndexAdded {
+                track,
+                index,
+                position,
+            } => set(
+                DiffScope::Track(track),
+                PatchField::Index(index),
+                PatchValue::None,
+                PatchValue::Duration(position),
+            ),
+            CueChange::IndexRemoved {
+                track,
+                index,
+                position,
+            } => set(
+                DiffScope::Track(track),
+                PatchField::Index(index),
+                PatchValue::Duration(position),
+                PatchValue::None,
+            ),
+            CueChange::IndexMoved {
+                track,
+                index,
+                old,
+                delta,
+                ..
+            } => PatchOp::ShiftIndex {
+                track,
+                index,
+                old,
+                delta,
+            },
+        }
+    }
+}
+
+/// An operation that does not apply to the sheet, and the value it found instead
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchConflict {
+    pub op: PatchOp,
+    pub found: PatchValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// The line of the patch text, starting at 1, can not be read
+    Parse { line: usize, message: String },
+    /// The diff reads INDEX values as durations, while a patch moves absolute positions
+    SumDiff,
+    /// Nothing is applied when an operation conflicts
+    Conflicts(Vec<PatchConflict>),
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            PatchError::SumDiff => write!(f, "the diff reads INDEX values as durations"),
+            PatchError::Conflicts(conflicts) => {
+                write!(f, "{} conflicting operation(s)", conflicts.len())?;
+                for conflict in conflicts {
+                    write!(f, "\n{} (found {})", conflict.op, conflict.found)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+/// A list of corrections to send and apply to a sheet.
+///
+/// A patch is written as text, one operation per line:
+///
+/// ```text
+/// ADD TRACK 04 AUDIO
+/// REMOVE TRACK 05
+/// RENUMBER TRACK 06 -> 05
+/// SET SHEET CATALOG - -> "0724349831627"
+/// SET TRACK 03 CDTEXT TITLE "Intro" -> "Intro (Live)"
+/// SET SHEET REM DATE [ "1998" ] -> [ "1999" ]
+/// SET SHEET BARE REM [ ] -> [ "Ripped without errors" ]
+/// SET TRACK 03 FILE "a.wav" WAVE -> -
+/// SET TRACK 03 FILE INDEX 02 - -> "b.wav" WAVE
+/// SET TRACK 03 FLAGS [ ] -> [ DCP PRE ]
+/// SET TRACK 07 INDEX 00 - -> 03:58:40
+/// SHIFT TRACK 07 INDEX 01 03:58:28 +12
+/// ```
+///
+/// `-` stands for no value. Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CuePatch {
+    ops: Vec<PatchOp>,
+}
+
+impl CuePatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Operations that turn the old sheet of `diff` into the new one.
+    ///
+    /// A moved INDEX is shifted by the same number of frames. The diff must be made
+    /// with `sum` unset, as positions in a patch are absolute.
+    pub fn from_diff(diff: &CueDiff) -> Result<Self, PatchError> {
+        if diff.sum() {
+            return Err(PatchError::SumDiff);
+        }
+        Ok(Self {
+            ops: diff.changes().iter().map(PatchOp::from).collect(),
+        })
+    }
+
+    pub fn push(&mut self, op: PatchOp) -> &mut Self {
+        self.ops.push(op);
+        self
+    }
+
+    pub fn ops(&self) -> &[PatchOp] {
+        &self.ops
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl Display for CuePatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for op in &self.ops {
+            writeln!(f, "{}", op)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+}
+
+/// Tokens of one line of a patch
+struct Tokens {
+    tokens: std::vec::IntoIter<Token>,
+}
+
+impl Tokens {
+    fn new(line: &str) -> Result<Self, String> {
+        let mut tokens = vec![];
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => (),
+                '"' => {
+                    let mut value = String::new();
+                    loop {
+                        match chars.next() {
+                            None => return Err("unterminated string".to_string()),
+                            Some('"') => break,
+                            Some('\\') => match chars.next() {
+                                Some('n') => value.push('\n'),
+                                Some(c) => value.push(c),
+                                None => return Err("unterminated string".to_string()),
+                            },
+                            Some(c) => value.push(c),
+                        }
+                    }
+                    tokens.push(Token::Quoted(value))
+                }
+                c => {
+                    let mut word = String::from(c);
+                    while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '"') {
+                        word.push(c)
+                    }
+                    tokens.push(Token::Word(word))
+                }
+            }
+        }
+        Ok(Self {
+            tokens: tokens.into_iter(),
+        })
+    }
+
+    fn word(&mut self) -> Result<String, String> {
+        match self.tokens.next() {
+            Some(Token::Word(word)) => Ok(word),
+            Some(Token::Quoted(value)) => Err(format!("unexpected string {}", quote(&value))),
+            None => Err("unexpected end of line".to_string()),
+        }
+    }
+
+    fn expect(&mut self, keyword: &str) -> Result<(), String> {
+        match self.word()? {
+            word if word.eq_ignore_ascii_case(keyword) => Ok(()),
+            word => Err(format!("expected {}, found {}", keyword, word)),
+        }
+    }
+
+    fn number(&mut self) -> Result<u32, String> {
+        let word = self.word()?;
+        word.parse().map_err(|_| format!("invalid number {}", word))
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        match self.tokens.next() {
+            None => Ok(()),
+            Some(Token::Word(word)) => Err(format!("unexpected {}", word)),
+            Some(Token::Quoted(value)) => Err(format!("unexpected string {}", quote(&value))),
+        }
+    }
+
+    fn none_or<T>(
+        &mut self,
+        value: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<Option<T>, String> {
+        match self.tokens.as_slice().first() {
+            Some(Token::Word(word)) if word == "-" => {
+                self.tokens.next();
+                Ok(None)
+            }
+            _ => value(self).map(Some),
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, String> {
+        match self.tokens.next() {
+            Some(Token::Quoted(value)) => Ok(value),
+            Some(Token::Word(word)) => Err(format!("expected a string, found {}", word)),
+            None => Err("unexpected end of line".to_string()),
+        }
+    }
+
+    /// Words or strings between `[` and `]`
+    fn list(&mut self) -> Result<Vec<Token>, String> {
+        self.expect("[")?;
+        let mut values = vec![];
+        loop {
+            match self.tokens.next() {
+                Some(Token::Word(word)) if word == "]" => return Ok(values),
+                Some(token) => values.push(token),
+                None => return Err("unterminated list".to_string()),
+            }
+        }
+    }
+
+    fn scope(&mut self) -> Result<DiffScope, String> {
+        match self.word()?.to_ascii_uppercase().as_str() {
+            "SHEET" => Ok(DiffScope::Sheet),
+            "TRACK" => self.number().map(DiffScope::Track),
+            word => Err(format!("expected SHEET or TRACK, found {}", word)),
+        }
+    }
+
+    fn field(&mut self) -> Result<PatchField, String> {
+        let field = match self.word()?.to_ascii_uppercase().as_str() {
+            "CATALOG" => PatchField::Catalog,
+            "FILE" => match self.tokens.as_slice().first() {
+                Some(Token::Word(word)) if word.eq_ignore_ascii_case("INDEX") => {
+                    self.tokens.next();
+                    PatchField::IndexFile(self.number()?)
+                }
+                _ => PatchField::File,
+            },
+            "MODE" => PatchField::Mode,
+            "CDTEXT" => {
+                let key = self.word()?;
+                match CueCdText::from_key(&key, "") {
+                    Some(cd_text) => PatchField::CdText(cd_text.key().to_string()),
+                    None => return Err(format!("unknown CD-Text keyword {}", key)),
+                }
+            }
+            "REM" => PatchField::Rem(self.word()?.to_ascii_uppercase()),
+            "BARE" => {
+                self.expect("REM")?;
+                PatchField::BareRems
+            }
+            "FLAGS" => PatchField::Flags,
+            "PREGAP" => PatchField::Pregap,
+            "POSTGAP" => PatchField::Postgap,
+            "INDEX" => PatchField::Index(self.number()?),
+            word => return Err(format!("unknown field {}", word)),
+        };
+        Ok(field)
+    }
+
+    fn mode(&mut self) -> Result<CueTrackMode, String> {
+        let word = self.word()?;
+        parse_named(&MODES, &word).ok_or(format!("unknown track mode {}", word))
+    }
+
+    fn duration(&mut self) -> Result<CueDuration, String> {
+        let word = self.word()?;
+        let parts = word
+            .split(':')
+            .map(str::parse::<u32>)
+            .collect::<Result<Vec<_>, _>>();
+        let frames = match parts.as_deref() {
+            Ok([min, sec, frame]) if *sec < 60 && *frame < FRAMES_PER_SECOND => min
+                .checked_mul(60)
+                .and_then(|secs| secs.checked_add(*sec))
+                .and_then(|secs| secs.checked_mul(FRAMES_PER_SECOND))
+                .and_then(|frames| frames.checked_add(*frame)),
+            _ => None,
+        };
+        frames
+            .map(CueDuration::from_frames)
+            .ok_or(format!("invalid duration {}", word))
+    }
+
+    fn value(&mut self, field: &PatchField) -> Result<PatchValue, String> {
+        let value = match field {
+            PatchField::Catalog | PatchField::CdText(_) => {
+                PatchValue::text(self.none_or(Self::quoted)?)
+            }
+            PatchField::File | PatchField::IndexFile(_) => {
+                PatchValue::file(self.none_or(|tokens| {
+                    let name = tokens.quoted()?;
+                    let format = tokens.word()?;
+                    match parse_named(&FORMATS, &format) {
+                        Some(format) => Ok((name, format)),
+                        None => Err(format!("unknown file format {}", format)),
+                    }
+                })?)
+            }
+            PatchField::Mode => PatchValue::Mode(self.mode()?),
+            PatchField::Rem(_) | PatchField::BareRems => {
+                let values = self.list()?.into_iter().map(|token| match token {
+                    Token::Quoted(value) => Ok(value),
+                    Token::Word(word) => Err(format!("expected a string, found {}", word)),
+                });
+                PatchValue::List(values.collect::<Result<_, _>>()?)
+            }
+            PatchField::Flags => {
+                let flags = self.list()?.into_iter().map(|token| match token {
+                    Token::Word(word) => {
+                        parse_named(&FLAGS, &word).ok_or(format!("unknown flag {}", word))
+                    }
+                    Token::Quoted(value) => Err(format!("unexpected string {}", quote(&value))),
+                });
+                let mut flags = flags.collect::<Result<Vec<_>, _>>()?;
+                flags.sort();
+                flags.dedup();
+                PatchValue::Flags(flags)
+            }
+            PatchField::Pregap | PatchField::Postgap | PatchField::Index(_) => {
+                PatchValue::duration(self.none_or(Self::duration)?)
+            }
+        };
+        Ok(value)
+    }
+
+    fn op(&mut self) -> Result<PatchOp, String> {
+        let op = match self.word()?.to_ascii_uppercase().as_str() {
+            "ADD" => {
+                self.expect("TRACK")?;
+                let track = self.number()?;
+                let mode = self.mode()?;
+                PatchOp::AddTrack { track, mode }
+            }
+            "REMOVE" => {
+                self.expect("TRACK")?;
+                PatchOp::RemoveTrack(self.number()?)
+            }
+            "RENUMBER" => {
+                self.expect("TRACK")?;
+                let from = self.number()?;
+                self.expect("->")?;
+                let to = self.number()?;
+                PatchOp::RenumberTrack { from, to }
+            }
+            "SET" => {
+                let scope = self.scope()?;
+                let field = self.field()?;
+                let old = self.value(&field)?;
+                self.expect("->")?;
+                let new = self.value(&field)?;
+                PatchOp::Set {
+                    scope,
+                    field,
+                    old,
+                    new,
+                }
+            }
+            "SHIFT" => {
+                self.expect("TRACK")?;
+                let track = self.number()?;
+                self.expect("INDEX")?;
+                let index = self.number()?;
+                let old = self.duration()?;
+                let word = self.word()?;
+                let delta = word
+                    .parse()
+                    .map_err(|_| format!("invalid frame count {}", word))?;
+                PatchOp::ShiftIndex {
+                    track,
+                    index,
+                    old,
+                    delta,
+                }
+            }
+            word => return Err(format!("unknown operation {}", word)),
+        };
+        self.end()?;
+        Ok(op)
+    }
+}
+
+/// The value of `values` written as `name`, ignoring case
+fn parse_named<T: Display + Copy>(values: &[T], name: &str) -> Option<T> {
+    values
+        .iter()
+        .find(|value| value.to_string().eq_ignore_ascii_case(name))
+        .copied()
+}
+
+impl FromStr for CuePatch {
+    type Err = PatchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ops = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let op = Tokens::new(line)
+                .and_then(|mut tokens| tokens.op())
+                .map_err(|message| PatchError::Parse {
+                    line: i + 1,
+                    message,
+                })?;
+            ops.push(op)
+        }
+        Ok(Self { ops })
+    }
+}
+
+/// Replace every REM entry `key` with `values`, at the place of the first
+fn set_rems(rems: &mut Vec<RemEntry>, key: &str, values: &[String]) {
+    let at = rems
+        .iter()
+        .position(|rem| rem.is(key))
+        .unwrap_or(rems.len());
+    let following = rems.split_off(at);
+    rems.extend(values.iter().map(|value| RemEntry::new(key, Some(value))));
+    rems.extend(following.into_iter().filter(|rem| !rem.is(key)));
+}
+
+/// Replace every REM line without value with `texts`, at the place of the first
+fn set_bare_rems(rems: &mut Vec<RemEntry>, texts: &[String]) {
+    let at = rems
+        .iter()
+        .position(|rem| rem.value().is_none())
+        .unwrap_or(rems.len());
+    let following = rems.split_off(at);
+    rems.extend(texts.iter().map(|text| RemEntry::new(text, None)));
+    rems.extend(following.into_iter().filter(|rem| rem.value().is_some()));
+}
+
+/// Text of every REM line without value
+fn bare_rems<'a>(rems: impl Iterator<Item = &'a RemEntry>) -> PatchValue {
+    let texts = rems.filter(|rem| rem.value().is_none()).map(RemEntry::key);
+    PatchValue::List(texts.map(str::to_string).collect())
+}
+
+fn set_cd_text_value(cd_texts: &mut Vec<CueCdText>, key: &str, value: &PatchValue) {
+    match value {
+        PatchValue::Text(text) => {
+            if let Some(cd_text) = CueCdText::from_key(key, text) {
+                set_cd_text(cd_texts, cd_text)
+            }
+        }
+        _ => cd_texts.retain(|cd_text| cd_text.key() != key),
+    }
+}
+
+/// Sheet being patched, with its tracks by number
+struct PatchTarget {
+    sheet: CueSheet,
+    tracks: BTreeMap<u32, CueTrack>,
+}
+
+impl PatchTarget {
+    /// Current value of `field`, `None` if the field does not exist in the scope
+    fn get(&self, scope: DiffScope, field: &PatchField) -> Option<PatchValue> {
+        let track = match scope {
+            DiffScope::Sheet => {
+                let sheet = &self.sheet;
+                let value = match field {
+                    PatchField::Catalog => PatchValue::text(sheet.catalog().map(str::to_string)),
+                    PatchField::File => {
+                        PatchValue::File(sheet.file_name().to_string(), sheet.file_format())
+                    }
+                    PatchField::CdText(key) => {
+                        PatchValue::text(sheet.cd_text(key).map(str::to_string))
+                    }
+                    PatchField::Rem(key) => {
+                        PatchValue::List(sheet.rem_values(key).map(str::to_string).collect())
+                    }
+                    PatchField::BareRems => bare_rems(sheet.rems()),
+                    _ => return None,
+                };
+                return Some(value);
+            }
+            DiffScope::Track(number) => self.tracks.get(&number)?,
+        };
+        let value = match field {
+            PatchField::Catalog => return None,
+            PatchField::File => PatchValue::file(
+                track
+                    .file()
+                    .map(|(name, format)| (name.to_string(), format)),
+            ),
+            PatchField::IndexFile(index) => {
+                PatchValue::file(track.index_files().get(index).cloned())
+            }
+            PatchField::Mode => PatchValue::Mode(track.mode()),
+            PatchField::CdText(key) => PatchValue::text(track.cd_text(key).map(str::to_string)),
+            PatchField::Rem(key) => {
+                PatchValue::List(track.rem_values(key).map(str::to_string).collect())
+            }
+            PatchField::BareRems => bare_rems(track.rems()),
+            PatchField::Flags => PatchValue::Flags(track.flags().copied().collect()),
+            PatchField::Pregap => PatchValue::duration(track.pregap()),
+            PatchField::Postgap => PatchValue::duration(track.postgap()),
+            PatchField::Index(index) => PatchValue::duration(track.indexes().get(index).copied()),
+        };
+        Some(value)
+    }
+
+    /// Write `value` in `field`, which exists in the scope
+    fn set(&mut self, scope: DiffScope, field: &PatchField, value: &PatchValue) {
+        let track = match scope {
+            DiffScope::Sheet => {
+                let sheet = &mut self.sheet;
+                match (field, value) {
+                    (PatchField::Catalog, PatchValue::Text(catalog)) => {
+                        *sheet.catalog_mut() = Some(catalog.clone())
+                    }
+                    (PatchField::Catalog, _) => *sheet.catalog_mut() = None,
+                    (PatchField::File, PatchValue::File(name, format)) => {
+                        sheet.set_file(name, *format);
+                    }
+                    (PatchField::CdText(key), value) => {
+                        set_cd_text_value(sheet.cd_texts_mut(), key, value)
+                    }
+                    (PatchField::Rem(key), PatchValue::List(values)) => {
+                        set_rems(sheet.rems_mut(), key, values)
+                    }
+                    (PatchField::BareRems, PatchValue::List(texts)) => {
+                        set_bare_rems(sheet.rems_mut(), texts)
+                    }
+                    _ => (),
+                }
+                return;
+            }
+            DiffScope::Track(number) => match self.tracks.get_mut(&number) {
+                Some(track) => track,
+                None => return,
+            },
+        };
+        match (field, value) {
+            (PatchField::File, PatchValue::File(name, format)) => {
+                track.set_file(Some((name.clone(), *format)));
+            }
+            (PatchField::File, _) => {
+                track.set_file(None);
+            }
+            (PatchField::IndexFile(index), PatchValue::File(name, format)) => {
+                track
+                    .index_files_mut()
+                    .insert(*index, (name.clone(), *format));
+            }
+            (PatchField::IndexFile(index), _) => {
+                track.index_files_mut().remove(index);
+            }
+            (PatchField::Mode, PatchValue::Mode(mode)) => {
+                track.set_mode(*mode);
+            }
+            (PatchField::CdText(key), value) => set_cd_text_value(track.cd_texts_mut(), key, value),
+            (PatchField::Rem(key), PatchValue::List(values)) => {
+                set_rems(track.rems_mut(), key, values)
+            }
+            (PatchField::BareRems, PatchValue::List(texts)) => {
+                set_bare_rems(track.rems_mut(), texts)
+            }
+            (PatchField::Flags, PatchValue::Flags(flags)) => {
+                *track.flags_mut() = flags.iter().copied().collect()
+            }
+            (PatchField::Pregap, value) => *track.pregap_mut() = value.as_duration(),
+            (PatchField::Postgap, value) => *track.postgap_mut() = value.as_duration(),
+            (PatchField::Index(index), value) => match value.as_duration() {
+                Some(position) => {
+                    track.indexes_mut().insert(*index, position);
+                }
+                None => {
+                    track.indexes_mut().remove(index);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// Apply `op`, or the value found instead of the expected one
+    fn apply(&mut self, op: &PatchOp) -> Result<(), PatchValue> {
+        match op {
+            PatchOp::AddTrack { track, mode } => match self.tracks.get(track) {
+                Some(existing) => return Err(PatchValue::Mode(existing.mode())),
+                None => {
+                    self.tracks.insert(*track, CueTrack::new(*track, *mode));
+                }
+            },
+            PatchOp::RemoveTrack(track) => {
+                self.tracks.remove(track);
+            }
+            PatchOp::RenumberTrack { from, to } => {
+                if let Some(existing) = self.tracks.get(to) {
+                    return Err(PatchValue::Mode(existing.mode()));
+                }
+                let mut track = self.tracks.remove(from).ok_or(PatchValue::None)?;
+                track.set_number(*to);
+                self.tracks.insert(*to, track);
+            }
+            PatchOp::Set {
+                scope,
+                field,
+                old,
+                new,
+            } => {
+                let current = self.get(*scope, field).ok_or(PatchValue::None)?;
+                // a FILE to replace that the track does not have is not already removed
+                let missing_file = matches!(field, PatchField::File | PatchField::IndexFile(_))
+                    && current == PatchValue::None;
+                if current == *new && !missing_file {
+                    return Ok(());
+                }
+                if current != *old {
+                    return Err(current);
+                }
+                match (scope, field, new) {
+                    (DiffScope::Track(_), PatchField::Index(1), PatchValue::None)
+                    | (DiffScope::Sheet, PatchField::File, PatchValue::None) => {
+                        return Err(current)
+                    }
+                    _ => (),
+                }
+                self.set(*scope, field, new)
+            }
+            PatchOp::ShiftIndex {
+                track,
+                index,
+                old,
+                delta,
+            } => {
+                let position = self
+                    .tracks
+                    .get_mut(track)
+                    .and_then(|track| track.indexes_mut().get_mut(index))
+                    .ok_or(PatchValue::None)?;
+                let frames = u32::try_from(old.frames() as i64 + delta)
+                    .map_err(|_| PatchValue::Duration(*position))?;
+                let new = CueDuration::from_frames(frames);
+                if *position == new {
+                    return Ok(());
+                }
+                if *position != *old {
+                    return Err(PatchValue::Duration(*position));
+                }
+                *position = new;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// INDEX placed before the previous INDEX of the same file, or after the next one,
+/// with the position of that other INDEX
+fn misplaced_indexes(sheet: &CueSheet) -> BTreeMap<(u32, u32), CueDuration> {
+    let mut misplaced = BTreeMap::new();
+    for pair in sheet.index_entries(false).windows(2) {
+        let (previous, next) = (pair[0], pair[1]);
+        if previous.file == next.file && next.position < previous.position {
+            misplaced.insert((previous.track, previous.index), next.position);
+            misplaced.insert((next.track, next.index), previous.position);
+        }
+    }
+    misplaced
+}
+
+impl CueSheet {
+    /// Copy of the sheet with the operations of `patch` applied in order.
+    ///
+    /// An operation that sets a value conflicts when the current value is neither the
+    /// expected old value nor the new one, so values already set are left as they are.
+    /// A shift likewise conflicts when the INDEX is neither at its old position nor
+    /// already moved. Removing INDEX 01 of a track, the FILE of the sheet or a FILE
+    /// that is not there conflicts, and so does setting or shifting an INDEX before the
+    /// previous INDEX of its file or after the next one.
+    /// Every conflict is reported and the sheet is only patched if there is none.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn apply_patch(&self, patch: &CuePatch) -> Result<CueSheet, PatchError> {
+        let mut sheet = self.clone();
+        let tracks = std::mem::take(sheet.tracks_mut())
+            .into_iter()
+            .map(|track| (track.number(), track))
+            .collect();
+        let mut target = PatchTarget { sheet, tracks };
+        let mut conflicts = patch
+            .ops
+            .iter()
+            .filter_map(|op| {
+                target.apply(op).err().map(|found| PatchConflict {
+                    op: op.clone(),
+                    found,
+                })
+            })
+            .collect::<Vec<_>>();
+        let PatchTarget { mut sheet, tracks } = target;
+        *sheet.tracks_mut() = tracks.into_values().collect();
+
+        let misplaced = misplaced_indexes(&sheet);
+        for op in &patch.ops {
+            let moved = match op {
+                PatchOp::Set {
+                    scope: DiffScope::Track(track),
+                    field: PatchField::Index(index),
+                    ..
+                }
+                | PatchOp::ShiftIndex { track, index, .. } => (*track, *index),
+                _ => continue,
+            };
+            if let Some(position) = misplaced.get(&moved) {
+                conflicts.push(PatchConflict {
+                    op: op.clone(),
+                    found: PatchValue::Duration(*position),
+                })
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(PatchError::Conflicts(conflicts));
+        }
+        Ok(sheet)
+    }
+}
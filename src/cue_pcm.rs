@@ -0,0 +1,83 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use crate::cue_audio_info::{AudioInfo, PcmData};
+
+/// Number of samples copied at once
+const CHUNK_SAMPLES: u64 = 16 * 1024;
+
+/// Header of a PCM WAVE file holding `data_len` bytes of samples
+pub(crate) fn wav_header(
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+    data_len: u64,
+) -> Result<Vec<u8>, Error> {
+    let data_len = u32::try_from(data_len)
+        .ok()
+        .filter(|len| *len <= u32::MAX - 36)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "WAVE data larger than 4 GiB"))?;
+    let block_align = channels * bits_per_sample.div_ceil(8);
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + data_len).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&channels.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    header.extend_from_slice(&block_align.to_le_bytes());
+    header.extend_from_slice(&bits_per_sample.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&data_len.to_le_bytes());
+    Ok(header)
+}
+
+/// Convert samples read from `pcm` to the little-endian layout of WAVE files.
+fn to_wave_layout(buffer: &mut [u8], pcm: PcmData, bits_per_sample: u16) {
+    let width = bits_per_sample.div_ceil(8) as usize;
+    if !pcm.big_endian {
+        return;
+    }
+    match width {
+        // 8 bits samples are signed in AIFF and unsigned in WAVE
+        1 => buffer.iter_mut().for_each(|byte| *byte ^= 0x80),
+        _ => buffer
+            .chunks_exact_mut(width)
+            .for_each(|sample| sample.reverse()),
+    }
+}
+
+/// Copy the samples `start..end` of the file described by `info` as WAVE data.
+pub(crate) fn copy_samples<R: Read + Seek, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    info: &AudioInfo,
+    pcm: PcmData,
+    start: u64,
+    end: u64,
+) -> Result<(), Error> {
+    let block_align = info.block_align();
+    reader.seek(SeekFrom::Start(pcm.offset + start * block_align))?;
+    let mut buffer = vec![0u8; (CHUNK_SAMPLES * block_align) as usize];
+    let mut remaining = end.saturating_sub(start);
+    while remaining > 0 {
+        let samples = remaining.min(CHUNK_SAMPLES);
+        let chunk = &mut buffer[..(samples * block_align) as usize];
+        reader.read_exact(chunk)?;
+        to_wave_layout(chunk, pcm, info.bits_per_sample);
+        writer.write_all(chunk)?;
+        remaining -= samples;
+    }
+    Ok(())
+}
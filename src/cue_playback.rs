@@ -0,0 +1,119 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::collections::BTreeMap;
+
+use crate::cue_sheet::CueSheet;
+
+/// A contiguous range of samples played for one INDEX
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaybackSegment {
+    /// File the samples are read from, `None` for the virtual silence of PREGAP and POSTGAP
+    pub file: Option<String>,
+    /// First sample of the segment
+    pub start: u64,
+    /// Sample after the end of the segment, `None` when it runs to the end of a file of unknown length
+    pub end: Option<u64>,
+    pub track: u32,
+    pub index: u32,
+}
+
+impl PlaybackSegment {
+    pub fn is_silence(&self) -> bool {
+        self.file.is_none()
+    }
+
+    /// Number of samples in the segment, if known
+    pub fn len(&self) -> Option<u64> {
+        self.end.map(|end| end.saturating_sub(self.start))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+}
+
+impl CueSheet {
+    /// Ordered list of the segments to play the whole sheet.
+    ///
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    /// `file_lengths` holds the number of samples of the files of [`CueSheet::files`];
+    /// segments ending with a file missing from it have no end. A segment that starts
+    /// past the end of its file is empty and left out.
+    pub fn playback_plan(&self, sample_rate: u32, file_lengths: &[u64]) -> Vec<PlaybackSegment> {
+        let files = self.files();
+        let entries = self.index_entries(false);
+        let tracks = self
+            .tracks()
+            .map(|track| (track.number(), track))
+            .collect::<BTreeMap<_, _>>();
+        let mut segments = Vec::new();
+        let silence = |track, index, length: u64| PlaybackSegment {
+            file: None,
+            start: 0,
+            end: Some(length),
+            track,
+            index,
+        };
+
+        for (i, entry) in entries.iter().enumerate() {
+            let track = tracks[&entry.track];
+            let first_of_track = i == 0 || entries[i - 1].track != entry.track;
+            if let Some(pregap) = track.pregap().filter(|_| first_of_track) {
+                segments.push(silence(entry.track, 0, pregap.samples(sample_rate)))
+            }
+
+            let start = entry.position.samples(sample_rate);
+            let next = entries.get(i + 1);
+            let same_file = next.filter(|next| next.file == entry.file);
+            let end = match same_file {
+                Some(next) => Some(next.position.samples(sample_rate)),
+                None => file_lengths.get(entry.file).copied(),
+            };
+            segments.push(PlaybackSegment {
+                file: Some(files[entry.file].0.to_string()),
+                start,
+                end: end.map(|end| end.max(start)),
+                track: entry.track,
+                index: entry.index,
+            });
+
+            // The INDEX goes on in the next file until the next boundary
+            if let Some(next) = next.filter(|next| next.file != entry.file) {
+                let spanned = files.iter().enumerate().take(next.file + 1);
+                for (file, (name, _)) in spanned.skip(entry.file + 1) {
+                    let end = match file == next.file {
+                        true => Some(next.position.samples(sample_rate)),
+                        false => file_lengths.get(file).copied(),
+                    };
+                    segments.push(PlaybackSegment {
+                        file: Some(name.to_string()),
+                        start: 0,
+                        end,
+                        track: entry.track,
+                        index: entry.index,
+                    })
+                }
+            }
+
+            let last_of_track = next.is_none_or(|next| next.track != entry.track);
+            if let Some(postgap) = track.postgap().filter(|_| last_of_track) {
+                segments.push(silence(
+                    entry.track,
+                    entry.index,
+                    postgap.samples(sample_rate),
+                ))
+            }
+        }
+
+        segments.retain(|segment| !segment.is_empty());
+        segments
+    }
+}
@@ -0,0 +1,146 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::fmt::Display;
+
+use crate::{cue_duration::CueDuration, cue_sheet::CueSheet, cue_track::CueTrack};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PregapError {
+    NoTrack(u32),
+    /// The pregap of the track starts in a file and ends in another one
+    SpansFiles(u32),
+}
+
+impl Display for PregapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PregapError::NoTrack(track) => write!(f, "no TRACK {:02}", track),
+            PregapError::SpansFiles(track) => {
+                write!(f, "the pregap of TRACK {:02} spans two files", track)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PregapError {}
+
+/// INDEX of the converted track from which positions move, and by how many frames
+type PregapShift = Option<(u32, i64)>;
+
+impl CueSheet {
+    /// Move every position after INDEX `index` of `track` that is in the same file by `delta` frames.
+    fn shift_following(
+        tracks: &mut [CueTrack],
+        entries: &[(u32, u32, usize)],
+        from: (u32, u32),
+        delta: i64,
+    ) {
+        let start = entries.partition_point(|(track, index, _)| (*track, *index) < from);
+        let file = match entries.get(start) {
+            Some((_, _, file)) => *file,
+            None => return,
+        };
+        let following = entries[start..]
+            .iter()
+            .take_while(|(_, _, entry_file)| *entry_file == file);
+        for (number, index, _) in following {
+            let track = tracks.iter_mut().find(|track| track.number() == *number);
+            let position = track.and_then(|track| track.indexes_mut().get_mut(index));
+            if let Some(position) = position {
+                let frames = (position.frames() as i64 + delta).max(0);
+                *position = CueDuration::from_frames(frames as u32);
+            }
+        }
+    }
+
+    fn convert_pregap(
+        &mut self,
+        number: u32,
+        convert: fn(&mut CueTrack) -> Result<PregapShift, PregapError>,
+    ) -> Result<&mut Self, PregapError> {
+        let entries = self
+            .index_entries(false)
+            .into_iter()
+            .map(|entry| (entry.track, entry.index, entry.file))
+            .collect::<Vec<_>>();
+        let mut tracks = std::mem::take(self.tracks_mut())
+            .into_iter()
+            .collect::<Vec<_>>();
+        let result = match tracks.iter_mut().find(|track| track.number() == number) {
+            None => Err(PregapError::NoTrack(number)),
+            Some(track) => convert(track),
+        };
+        if let Ok(Some((index, delta))) = result {
+            Self::shift_following(&mut tracks, &entries, (number, index), delta);
+        }
+        *self.tracks_mut() = tracks.into_iter().collect();
+        result.map(|_| self)
+    }
+
+    /// Turn the INDEX 00 to INDEX 01 range of `track` into a PREGAP command.
+    ///
+    /// The pregap audio is considered removed from the file, so every following
+    /// position in the same file moves earlier by its length.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn index_to_pregap(&mut self, track: u32) -> Result<&mut Self, PregapError> {
+        self.convert_pregap(track, |track| {
+            let number = track.number();
+            let (index00, index01) = match (track.indexes().get(&0), track.indexes().get(&1)) {
+                (Some(index00), Some(index01)) => (*index00, *index01),
+                _ => return Ok(None),
+            };
+            if track.index_files().contains_key(&1) {
+                return Err(PregapError::SpansFiles(number));
+            }
+            let gap = index01.saturating_sub(index00);
+            let _ = track.indexes_mut().remove(&0);
+            let pregap = track.pregap_mut();
+            *pregap = Some(pregap.unwrap_or(CueDuration::zero()) + gap);
+            Ok(Some((1, -(gap.frames() as i64))))
+        })
+    }
+
+    /// Turn the PREGAP command of `track` into audio between INDEX 00 and INDEX 01.
+    ///
+    /// The silence is considered inserted in the file at the start of the track, so
+    /// every following position in the same file moves later by its length.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn pregap_to_index(&mut self, track: u32) -> Result<&mut Self, PregapError> {
+        self.convert_pregap(track, |track| {
+            let (pregap, start) = match (track.pregap(), track.first_position()) {
+                (Some(pregap), Some(start)) => (pregap, start),
+                _ => return Ok(None),
+            };
+            *track.pregap_mut() = None;
+            // INDEX 00 stays at the start of the silence, the rest of the track follows it
+            let _ = track.indexes_mut().insert(0, start);
+            Ok(Some((1, pregap.frames() as i64)))
+        })
+    }
+
+    /// [`CueSheet::index_to_pregap`] for every track
+    pub fn indexes_to_pregaps(&mut self) -> Result<&mut Self, PregapError> {
+        let numbers = self.tracks().map(CueTrack::number).collect::<Vec<_>>();
+        for number in numbers {
+            let _ = self.index_to_pregap(number)?;
+        }
+        Ok(self)
+    }
+
+    /// [`CueSheet::pregap_to_index`] for every track
+    pub fn pregaps_to_indexes(&mut self) -> Result<&mut Self, PregapError> {
+        let numbers = self.tracks().map(CueTrack::number).collect::<Vec<_>>();
+        for number in numbers {
+            let _ = self.pregap_to_index(number)?;
+        }
+        Ok(self)
+    }
+}
@@ -0,0 +1,348 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::fmt::Display;
+
+use crate::{cue_sheet::CueSheet, cue_track::CueTrack, util::cue_format_string_value};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemError {
+    /// The key has no typed value
+    UnknownKey(String),
+    /// The value does not match the type of the key
+    InvalidValue { key: String, value: String },
+}
+
+impl Display for RemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemError::UnknownKey(key) => write!(f, "REM {} has no typed value", key),
+            RemError::InvalidValue { key, value } => {
+                write!(f, "invalid value for REM {}: \"{}\"", key, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RemError {}
+
+/// Value of REM DATE, a year or a full date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RemDate {
+    Year(u16),
+    Full { year: u16, month: u8, day: u8 },
+}
+
+impl RemDate {
+    fn parse(value: &str) -> Option<Self> {
+        let parts = value.split('-').collect::<Vec<_>>();
+        let number = |part: &str, len: usize| match part.len() == len
+            && part.bytes().all(|byte| byte.is_ascii_digit())
+        {
+            true => part.parse::<u16>().ok(),
+            false => None,
+        };
+        match parts.as_slice() {
+            [year] => number(year, 4).map(RemDate::Year),
+            [year, month, day] => {
+                let (year, month, day) = (number(year, 4)?, number(month, 2)?, number(day, 2)?);
+                let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+                let days = match month {
+                    2 if leap => 29,
+                    2 => 28,
+                    4 | 6 | 9 | 11 => 30,
+                    1..=12 => 31,
+                    _ => return None,
+                };
+                (1..=days).contains(&day).then_some(RemDate::Full {
+                    year,
+                    month: month as u8,
+                    day: day as u8,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn year(&self) -> u16 {
+        match self {
+            RemDate::Year(year) | RemDate::Full { year, .. } => *year,
+        }
+    }
+}
+
+impl Display for RemDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemDate::Year(year) => write!(f, "{:04}", year),
+            RemDate::Full { year, month, day } => {
+                write!(f, "{:04}-{:02}-{:02}", year, month, day)
+            }
+        }
+    }
+}
+
+/// REM fields written by the common rippers, such as EAC and foobar2000
+#[derive(Debug, Clone, PartialEq)]
+pub enum CueRem {
+    Date(RemDate),
+    Genre(String),
+    /// FreeDB disc ID
+    DiscId(u32),
+    Comment(String),
+    DiscNumber(u32),
+    TotalDiscs(u32),
+    Composer(String),
+    /// Album gain in dB
+    ReplayGainAlbumGain(f64),
+    /// Album peak, between 0 and 1
+    ReplayGainAlbumPeak(f64),
+    /// Track gain in dB
+    ReplayGainTrackGain(f64),
+    /// Track peak, between 0 and 1
+    ReplayGainTrackPeak(f64),
+}
+
+impl CueRem {
+    /// Keys of the typed REM fields
+    pub const KEYS: [&'static str; 11] = [
+        "DATE",
+        "GENRE",
+        "DISCID",
+        "COMMENT",
+        "DISCNUMBER",
+        "TOTALDISCS",
+        "COMPOSER",
+        "REPLAYGAIN_ALBUM_GAIN",
+        "REPLAYGAIN_ALBUM_PEAK",
+        "REPLAYGAIN_TRACK_GAIN",
+        "REPLAYGAIN_TRACK_PEAK",
+    ];
+
+    /// Parse the value of REM `key`, as written in a sheet with its surrounding quotes
+    pub fn parse(key: &str, value: &str) -> Result<Self, RemError> {
+        Self::from_value(key, &unquote(value.trim()))
+    }
+
+    /// Typed value of REM `key` from its value without quotes
+    fn from_value(key: &str, unquoted: &str) -> Result<Self, RemError> {
+        let key = key.to_ascii_uppercase();
+        let gain = || {
+            unquoted
+                .strip_suffix("dB")
+                .unwrap_or(unquoted)
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|gain| gain.is_finite())
+        };
+        let peak = || {
+            unquoted
+                .parse::<f64>()
+                .ok()
+                .filter(|peak| (0.0..=1.0).contains(peak))
+        };
+        let number = || unquoted.parse::<u32>().ok().filter(|number| *number > 0);
+        let rem = match key.as_str() {
+            "DATE" => RemDate::parse(unquoted).map(CueRem::Date),
+            "GENRE" => Some(CueRem::Genre(unquoted.to_string())),
+            "DISCID" => {
+                match unquoted.len() == 8 && unquoted.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+                    true => u32::from_str_radix(unquoted, 16).ok().map(CueRem::DiscId),
+                    false => None,
+                }
+            }
+            "COMMENT" => Some(CueRem::Comment(unquoted.to_string())),
+            "DISCNUMBER" => number().map(CueRem::DiscNumber),
+            "TOTALDISCS" => number().map(CueRem::TotalDiscs),
+            "COMPOSER" => Some(CueRem::Composer(unquoted.to_string())),
+            "REPLAYGAIN_ALBUM_GAIN" => gain().map(CueRem::ReplayGainAlbumGain),
+            "REPLAYGAIN_ALBUM_PEAK" => peak().map(CueRem::ReplayGainAlbumPeak),
+            "REPLAYGAIN_TRACK_GAIN" => gain().map(CueRem::ReplayGainTrackGain),
+            "REPLAYGAIN_TRACK_PEAK" => peak().map(CueRem::ReplayGainTrackPeak),
+            _ => return Err(RemError::UnknownKey(key)),
+        };
+        rem.ok_or_else(|| RemError::InvalidValue {
+            key,
+            value: unquoted.to_string(),
+        })
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            CueRem::Date(_) => "DATE",
+            CueRem::Genre(_) => "GENRE",
+            CueRem::DiscId(_) => "DISCID",
+            CueRem::Comment(_) => "COMMENT",
+            CueRem::DiscNumber(_) => "DISCNUMBER",
+            CueRem::TotalDiscs(_) => "TOTALDISCS",
+            CueRem::Composer(_) => "COMPOSER",
+            CueRem::ReplayGainAlbumGain(_) => "REPLAYGAIN_ALBUM_GAIN",
+            CueRem::ReplayGainAlbumPeak(_) => "REPLAYGAIN_ALBUM_PEAK",
+            CueRem::ReplayGainTrackGain(_) => "REPLAYGAIN_TRACK_GAIN",
+            CueRem::ReplayGainTrackPeak(_) => "REPLAYGAIN_TRACK_PEAK",
+        }
+    }
+
+    /// Value as stored in the sheet, without quotes
+    pub fn value(&self) -> String {
+        match self {
+            CueRem::Date(date) => date.to_string(),
+            CueRem::Genre(s) | CueRem::Comment(s) | CueRem::Composer(s) => s.clone(),
+            CueRem::DiscId(id) => format!("{:08X}", id),
+            CueRem::DiscNumber(n) | CueRem::TotalDiscs(n) => n.to_string(),
+            CueRem::ReplayGainAlbumGain(gain) | CueRem::ReplayGainTrackGain(gain) => {
+                format!("{:+.2} dB", gain)
+            }
+            CueRem::ReplayGainAlbumPeak(peak) | CueRem::ReplayGainTrackPeak(peak) => {
+                format!("{:.6}", peak)
+            }
+        }
+    }
+}
+
+impl Display for CueRem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "REM {} {}",
+            self.key(),
+            format_rem_value(self.key(), &self.value())
+        )
+    }
+}
+
+/// A REM line, kept in the order of the sheet.
+///
+/// The key may repeat, and a bare `REM` or `REM KEY` has no value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RemEntry {
+    key: String,
+    value: Option<String>,
+}
+
+impl RemEntry {
+    /// Entry `key` with `value` stored as given
+    pub fn new(key: &str, value: Option<&str>) -> Self {
+        Self {
+            key: key.to_string(),
+            value: value.map(str::to_string),
+        }
+    }
+
+    /// Entry of a REM line as written in a sheet, such as `REM GENRE "Rock"`.
+    ///
+    /// The value is the rest of the line after the key, with its surrounding quotes
+    /// removed. A line with a single word after `REM` has no value.
+    pub fn from_line(line: &str) -> Self {
+        let line = line.trim();
+        let rest = match line.get(..3) {
+            Some(rem) if rem.eq_ignore_ascii_case("REM") => line[3..].trim_start(),
+            _ => line,
+        };
+        match rest.split_once(char::is_whitespace) {
+            Some((key, value)) => Self::new(key, Some(&unquote(value.trim()))),
+            None => Self::new(rest, None),
+        }
+    }
+
+    /// Word after `REM` when the entry has a value. An entry without value keeps the
+    /// whole text of its line, as given to `add_bare_rem`, and is empty for a bare `REM`.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Rest of the line, without quotes
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    /// Whether the key is `key`, ignoring case
+    pub fn is(&self, key: &str) -> bool {
+        self.key.eq_ignore_ascii_case(key)
+    }
+}
+
+impl Display for RemEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "REM")?;
+        if !self.key.is_empty() {
+            write!(f, " {}", self.key)?;
+        }
+        match &self.value {
+            Some(value) => write!(f, " {}", format_rem_value(&self.key, value)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `value` between quotes, with its quotes written as `\"`
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\\\""))
+}
+
+/// Value of a REM written by [`quote`], or `value` itself if it is not quoted
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .map_or(value.to_string(), |quoted| quoted.replace("\\\"", "\""))
+}
+
+/// Quote the value of REM `key` the way rippers write it.
+///
+/// Text fields are always quoted, the other typed fields never are, and untyped
+/// values are quoted when they contain a space or a quote.
+pub(crate) fn format_rem_value(key: &str, value: &str) -> String {
+    match key.to_ascii_uppercase().as_str() {
+        "GENRE" | "COMMENT" | "COMPOSER" => quote(value),
+        key if CueRem::KEYS.contains(&key) => value.to_string(),
+        _ if value.contains('"') => quote(value),
+        _ => cue_format_string_value(value),
+    }
+}
+
+impl CueSheet {
+    /// Append the typed REM field `rem`
+    pub fn add_rem_field(&mut self, rem: CueRem) -> &mut Self {
+        self.add_rem(rem.key(), &rem.value())
+    }
+
+    /// Set the typed REM field `rem`, replacing every entry of the same key
+    pub fn set_rem_field(&mut self, rem: CueRem) -> &mut Self {
+        self.set_rem(rem.key(), &rem.value())
+    }
+
+    /// Typed value of the first REM entry `key`, `None` if the sheet has no such entry
+    pub fn rem_field(&self, key: &str) -> Result<Option<CueRem>, RemError> {
+        self.rem(key)
+            .map(|value| CueRem::from_value(key, value))
+            .transpose()
+    }
+}
+
+impl CueTrack {
+    /// Append the typed REM field `rem`
+    pub fn add_rem_field(&mut self, rem: CueRem) -> &mut Self {
+        self.add_rem(rem.key(), &rem.value())
+    }
+
+    /// Set the typed REM field `rem`, replacing every entry of the same key
+    pub fn set_rem_field(&mut self, rem: CueRem) -> &mut Self {
+        self.set_rem(rem.key(), &rem.value())
+    }
+
+    /// Typed value of the first REM entry `key`, `None` if the track has no such entry
+    pub fn rem_field(&self, key: &str) -> Result<Option<CueRem>, RemError> {
+        self.rem(key)
+            .map(|value| CueRem::from_value(key, value))
+            .transpose()
+    }
+}
@@ -0,0 +1,172 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::fmt::Display;
+
+use crate::{
+    cue_duration::{CueDuration, FRAMES_PER_SECOND},
+    cue_sheet::CueSheet,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CueShift {
+    Later(CueDuration),
+    Earlier(CueDuration),
+    /// Signed number of samples at `sample_rate`, rounded to the nearest CD frame
+    /// since an INDEX can not hold a finer position. [`CueShift::frames`] gives the
+    /// shift that is applied.
+    Samples {
+        count: i64,
+        sample_rate: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftPolicy {
+    /// Positions that would become negative are set to 00:00:00
+    Clamp,
+    /// Positions that would become negative make the shift fail
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Nearest,
+    Down,
+    Up,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScaleReport {
+    /// Largest difference between a scaled position and its exact value, in CD frames
+    pub max_error: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftError {
+    NegativePosition {
+        track: u32,
+        index: u32,
+        position: CueDuration,
+    },
+    /// A shift in samples with a sample rate of 0
+    ZeroSampleRate,
+}
+
+impl Display for ShiftError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShiftError::NegativePosition {
+                track,
+                index,
+                position,
+            } => write!(
+                f,
+                "TRACK {:02} INDEX {:02} {} would be moved before 00:00:00",
+                track, index, position
+            ),
+            ShiftError::ZeroSampleRate => write!(f, "a shift in samples needs a sample rate"),
+        }
+    }
+}
+
+impl std::error::Error for ShiftError {}
+
+impl CueShift {
+    /// Signed number of CD frames, a shift in samples is rounded to the nearest frame
+    pub fn frames(&self) -> Result<i64, ShiftError> {
+        match self {
+            CueShift::Later(duration) => Ok(duration.frames() as i64),
+            CueShift::Earlier(duration) => Ok(-(duration.frames() as i64)),
+            CueShift::Samples { sample_rate: 0, .. } => Err(ShiftError::ZeroSampleRate),
+            CueShift::Samples { count, sample_rate } => {
+                let scaled = count * FRAMES_PER_SECOND as i64;
+                let rate = *sample_rate as i64;
+                Ok((scaled + scaled.signum() * rate / 2) / rate)
+            }
+        }
+    }
+}
+
+impl Rounding {
+    /// Round `numerator / denominator` to a whole number
+    fn divide(&self, numerator: u64, denominator: u64) -> u64 {
+        match self {
+            Rounding::Nearest => (numerator + denominator / 2) / denominator,
+            Rounding::Down => numerator / denominator,
+            Rounding::Up => numerator.div_ceil(denominator),
+        }
+    }
+}
+
+impl CueSheet {
+    /// Copy of the sheet where every INDEX of every track is moved by `shift`.
+    ///
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    /// A shift in samples is rounded to whole CD frames, see [`CueShift::frames`].
+    pub fn shifted(&self, shift: CueShift, policy: ShiftPolicy) -> Result<CueSheet, ShiftError> {
+        let delta = shift.frames()?;
+        let mut sheet = self.clone();
+        let tracks = std::mem::take(sheet.tracks_mut());
+        for mut track in tracks {
+            let number = track.number();
+            for (index, position) in track.indexes_mut().iter_mut() {
+                let frames = position.frames() as i64 + delta;
+                if frames < 0 && policy == ShiftPolicy::Reject {
+                    return Err(ShiftError::NegativePosition {
+                        track: number,
+                        index: *index,
+                        position: *position,
+                    });
+                }
+                *position = CueDuration::from_frames(frames.max(0) as u32);
+            }
+            let _ = sheet.tracks_mut().insert(track);
+        }
+        Ok(sheet)
+    }
+
+    /// Copy of the sheet where every INDEX, PREGAP and POSTGAP is multiplied by
+    /// `numerator / denominator`, for audio that was resampled or time-stretched.
+    ///
+    /// For example, a PAL speed-up is undone with a ratio of 25/24.
+    ///
+    /// # Panics
+    /// If `denominator` is zero
+    pub fn scaled(
+        &self,
+        numerator: u32,
+        denominator: u32,
+        rounding: Rounding,
+    ) -> (CueSheet, ScaleReport) {
+        assert!(denominator != 0, "scaling ratio with a zero denominator");
+        let (numerator, denominator) = (numerator as u64, denominator as u64);
+        let mut max_error = 0;
+        let mut scale = |duration: &mut CueDuration| {
+            let exact = duration.frames() as u64 * numerator;
+            let frames = rounding.divide(exact, denominator);
+            max_error = max_error.max((frames * denominator).abs_diff(exact));
+            *duration = CueDuration::from_frames(frames as u32);
+        };
+
+        let mut sheet = self.clone();
+        let tracks = std::mem::take(sheet.tracks_mut());
+        for mut track in tracks {
+            track.indexes_mut().values_mut().for_each(&mut scale);
+            track.pregap_mut().iter_mut().for_each(&mut scale);
+            track.postgap_mut().iter_mut().for_each(&mut scale);
+            let _ = sheet.tracks_mut().insert(track);
+        }
+        let report = ScaleReport {
+            max_error: max_error as f64 / denominator as f64,
+        };
+        (sheet, report)
+    }
+}
@@ -12,33 +12,52 @@ use std::{
     collections::{BTreeMap, BTreeSet},
     fs::OpenOptions,
     io::Write,
-    ops::Add,
+    path::Path,
 };
 
 use crate::{
-    cue_cd_text::CueCdText,
+    cue_audio_info::{AudioInfo, ProbeError},
+    cue_cd_text::{set_cd_text, CdTextOrder, CueCdText},
+    cue_command::{repr_unknown_commands, CommandPosition, UnknownCommand},
     cue_duration::CueDuration,
     cue_file_format::CueFileFormat,
-    cue_track::{ComputeKind, CueTrack},
-    util::cue_format_string_value,
+    cue_file_resolver::{self, FileMatch, FileResolution, ResolveError},
+    cue_rem::RemEntry,
+    cue_track::CueTrack,
+    util::{cue_format_file, cue_format_string_value},
 };
 
-#[derive(Debug)]
+/// An INDEX with its absolute position and the file it belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IndexEntry {
+    pub(crate) track: u32,
+    pub(crate) index: u32,
+    /// Position of the file in [`CueSheet::files`]
+    pub(crate) file: usize,
+    pub(crate) position: CueDuration,
+}
+
+#[derive(Debug, Clone)]
 pub struct CueSheet {
     catalog: Option<String>,
     cd_text_file: Option<String>,
-    cd_texts: BTreeSet<CueCdText>,
-    rems: BTreeMap<String, String>,
+    cd_texts: Vec<CueCdText>,
+    rems: Vec<RemEntry>,
     file: (String, CueFileFormat),
     tracks: BTreeSet<CueTrack>,
+    unknown_commands: Vec<UnknownCommand>,
+    cd_text_order: CdTextOrder,
 }
 
+/// Sections of the sheet scope, in the order they are written
+const SHEET_SECTIONS: [&str; 5] = ["CATALOG", "CDTEXTFILE", "CDTEXT", "REM", "FILE"];
+
 impl CueSheet {
     fn repr_catalog(&self) -> String {
         self.catalog
             .iter()
             .map(|s| {
-                let s = cue_format_string_value(&s);
+                let s = cue_format_string_value(s);
                 format!("CATALOG {}\n", s)
             })
             .collect::<String>()
@@ -48,7 +67,7 @@ impl CueSheet {
         self.cd_text_file
             .iter()
             .map(|s| {
-                let s = cue_format_string_value(&s);
+                let s = cue_format_string_value(s);
                 format!("CDTEXTFILE {}\n", s)
             })
             .collect::<String>()
@@ -59,8 +78,9 @@ impl CueSheet {
             true => String::new(),
             false => {
                 let s = self
-                    .cd_texts
-                    .iter()
+                    .cd_text_order
+                    .sort(&self.cd_texts)
+                    .into_iter()
                     .map(CueCdText::to_string)
                     .collect::<Vec<String>>()
                     .join("\n");
@@ -70,60 +90,88 @@ impl CueSheet {
     }
 
     fn repr_rems(&self) -> String {
-        let mapper = |(key, value)| format!("REM {} {}\n", key, value);
-        match self.rems.is_empty() {
-            true => String::new(),
-            false => self.rems.iter().map(mapper).collect::<Vec<_>>().join(""),
-        }
+        self.rems.iter().map(|rem| format!("{}\n", rem)).collect()
     }
 
     fn repr_file(&self) -> String {
-        let (ref name, ref format) = self.file;
-        format!("FILE \"{}\" {}\n", name, format)
+        let (ref name, format) = self.file;
+        cue_format_file(name, format)
     }
 
     fn repr_tracks(&self, sum: bool) -> String {
         self.tracks
             .iter()
-            .scan(CueDuration::zero(), |state, track| {
-                let offset = *state;
-                *state = track.track_offset().add(*state);
-                let compute = if sum {
-                    Some(ComputeKind::Set(offset))
-                } else {
-                    None
-                };
-                Some(track.repr(true, compute))
+            .zip(self.index_positions(sum))
+            .map(|(track, positions)| match sum {
+                true => track.clone().with_indexes(positions).repr_ordered(
+                    true,
+                    None,
+                    self.cd_text_order,
+                ),
+                false => track.repr_ordered(true, None, self.cd_text_order),
             })
             .collect::<Vec<_>>()
             .join("\n")
     }
 
     pub fn repr(&self, sum: bool) -> String {
+        let commands =
+            |section| repr_unknown_commands(&self.unknown_commands, &SHEET_SECTIONS, section, "");
         let str_catalog = self.repr_catalog();
         let str_cd_text_file = self.repr_cdtextfile();
         let str_cd_texts = self.repr_cdtexts();
         let str_rems = self.repr_rems();
         let str_file = self.repr_file();
         let str_tracks = self.repr_tracks(sum);
+        let str_end = match (commands("END"), str_tracks.is_empty()) {
+            (end, _) if end.is_empty() => end,
+            (end, true) => end,
+            (end, false) => format!("\n{}", end.trim_end_matches('\n')),
+        };
         format!(
-            "{}{}{}{}{}{}",
-            str_catalog, str_cd_text_file, str_cd_texts, str_rems, str_file, str_tracks
+            "{}{}{}{}{}{}{}{}{}{}{}{}{}",
+            commands("START"),
+            str_catalog,
+            commands("CATALOG"),
+            str_cd_text_file,
+            commands("CDTEXTFILE"),
+            str_cd_texts,
+            commands("CDTEXT"),
+            str_rems,
+            commands("REM"),
+            str_file,
+            commands("FILE"),
+            str_tracks,
+            str_end
         )
     }
 }
 
 impl CueSheet {
     pub fn new(filename: &str, format: CueFileFormat) -> Self {
-        let sheet = Self {
+        Self {
             catalog: None,
             cd_text_file: None,
-            cd_texts: BTreeSet::new(),
-            rems: BTreeMap::new(),
+            cd_texts: Vec::new(),
+            rems: Vec::new(),
             file: (filename.to_string(), format),
             tracks: BTreeSet::new(),
-        };
-        sheet
+            unknown_commands: Vec::new(),
+            cd_text_order: CdTextOrder::default(),
+        }
+    }
+
+    /// Keep the line of a command the crate does not model, written back at `position`
+    pub fn add_unknown_command(&mut self, line: &str, position: CommandPosition) -> &mut Self {
+        self.unknown_commands
+            .push(UnknownCommand::new(line, position));
+        self
+    }
+
+    /// Order of the CD-Text commands of the sheet and of its tracks when written
+    pub fn set_cd_text_order(&mut self, order: CdTextOrder) -> &mut Self {
+        self.cd_text_order = order;
+        self
     }
 
     pub fn add_catalog(&mut self, catalog: &str) -> &mut Self {
@@ -133,61 +181,90 @@ impl CueSheet {
 
     pub fn add_arranger(&mut self, arranger: &str) -> &mut Self {
         let arranger = CueCdText::Arrager(arranger.to_owned());
-        let _ = self.cd_texts.insert(arranger);
+        set_cd_text(&mut self.cd_texts, arranger);
         self
     }
 
     pub fn add_composer(&mut self, composer: &str) -> &mut Self {
         let composer = CueCdText::Composer(composer.to_string());
-        let _ = self.cd_texts.insert(composer);
+        set_cd_text(&mut self.cd_texts, composer);
         self
     }
 
     pub fn add_disc_id(&mut self, composer: &str) -> &mut Self {
         let disc_id = CueCdText::DiscId(composer.to_owned());
-        let _ = self.cd_texts.insert(disc_id);
+        set_cd_text(&mut self.cd_texts, disc_id);
         self
     }
 
     pub fn add_genre(&mut self, genre: &str) -> &mut Self {
         let genre = CueCdText::Genre(genre.to_string());
-        let _ = self.cd_texts.insert(genre);
+        set_cd_text(&mut self.cd_texts, genre);
         self
     }
 
     pub fn add_iscr(&mut self, iscr: &str) -> &mut Self {
         let iscr = CueCdText::ISrc(iscr.to_string());
-        let _ = self.cd_texts.insert(iscr);
+        set_cd_text(&mut self.cd_texts, iscr);
         self
     }
 
     pub fn add_message(&mut self, message: &str) -> &mut Self {
         let message = CueCdText::Message(message.to_string());
-        let _ = self.cd_texts.insert(message);
+        set_cd_text(&mut self.cd_texts, message);
         self
     }
 
     pub fn add_performer(&mut self, performer: &str) -> &mut Self {
         let performer = CueCdText::Performer(performer.to_string());
-        let _ = self.cd_texts.insert(performer);
+        set_cd_text(&mut self.cd_texts, performer);
         self
     }
 
     pub fn add_songwriter(&mut self, songwriter: &str) -> &mut Self {
         let songwriter = CueCdText::SongWriter(songwriter.to_string());
-        let _ = self.cd_texts.insert(songwriter);
+        set_cd_text(&mut self.cd_texts, songwriter);
         self
     }
 
     pub fn add_title(&mut self, title: &str) -> &mut Self {
         let title = CueCdText::Title(title.to_owned());
-        let _ = self.cd_texts.insert(title);
+        set_cd_text(&mut self.cd_texts, title);
         self
     }
 
+    /// Append the REM entry `key` with `value`, stored as given
     pub fn add_rem(&mut self, key: &str, value: &str) -> &mut Self {
         let key = key.to_ascii_uppercase();
-        let _ = self.rems.insert(key, value.to_owned());
+        self.rems.push(RemEntry::new(&key, Some(value)));
+        self
+    }
+
+    /// Append a REM line without value, such as a bare `REM` if `text` is empty.
+    /// The whole of `text` is kept as the key of the entry.
+    pub fn add_bare_rem(&mut self, text: &str) -> &mut Self {
+        self.rems.push(RemEntry::new(text, None));
+        self
+    }
+
+    /// Replace every REM entry `key` with a single one, at the place of the first
+    pub fn set_rem(&mut self, key: &str, value: &str) -> &mut Self {
+        let entry = RemEntry::new(&key.to_ascii_uppercase(), Some(value));
+        match self.rems.iter().position(|rem| rem.is(key)) {
+            None => self.rems.push(entry),
+            Some(i) => {
+                self.rems[i] = entry;
+                let following = self.rems.split_off(i + 1);
+                self.rems
+                    .extend(following.into_iter().filter(|rem| !rem.is(key)));
+            }
+        }
+        self
+    }
+
+    /// Remove every REM entry `key`
+    pub fn remove_rems(&mut self, key: &str) -> &mut Self {
+        self.rems.retain(|rem| !rem.is(key));
         self
     }
 
@@ -211,3 +288,229 @@ impl CueSheet {
         file.write_all(str_repr.as_bytes())
     }
 }
+
+impl CueSheet {
+    pub fn tracks(&self) -> impl Iterator<Item = &CueTrack> {
+        self.tracks.iter()
+    }
+
+    pub(crate) fn tracks_mut(&mut self) -> &mut BTreeSet<CueTrack> {
+        &mut self.tracks
+    }
+
+    pub(crate) fn cd_texts_mut(&mut self) -> &mut Vec<CueCdText> {
+        &mut self.cd_texts
+    }
+
+    pub(crate) fn rems_mut(&mut self) -> &mut Vec<RemEntry> {
+        &mut self.rems
+    }
+
+    pub(crate) fn unknown_commands_mut(&mut self) -> &mut Vec<UnknownCommand> {
+        &mut self.unknown_commands
+    }
+
+    pub(crate) fn catalog_mut(&mut self) -> &mut Option<String> {
+        &mut self.catalog
+    }
+
+    pub fn catalog(&self) -> Option<&str> {
+        self.catalog.as_deref()
+    }
+
+    /// Absolute position of every INDEX, track by track.
+    ///
+    /// If `sum` is set, index values are read as durations like in [`CueSheet::repr`]:
+    /// each track starts where the previous one ends and each INDEX starts where the
+    /// previous INDEX of the track ends.
+    pub(crate) fn index_positions(&self, sum: bool) -> Vec<BTreeMap<u32, CueDuration>> {
+        match sum {
+            false => self
+                .tracks
+                .iter()
+                .map(|track| track.indexes().clone())
+                .collect(),
+            true => self
+                .tracks
+                .iter()
+                .scan(CueDuration::zero(), |offset, track| {
+                    if track.file().is_some() {
+                        *offset = CueDuration::zero();
+                    }
+                    let positions = track
+                        .indexes()
+                        .iter()
+                        .map(|(index, duration)| {
+                            if track.index_files().contains_key(index) {
+                                *offset = CueDuration::zero();
+                            }
+                            let position = *offset;
+                            *offset = *offset + *duration;
+                            (*index, position)
+                        })
+                        .collect();
+                    Some(positions)
+                })
+                .collect(),
+        }
+    }
+
+    /// Copy of the sheet where INDEX durations, as read by [`CueSheet::repr`] with `sum`,
+    /// are replaced by their absolute positions.
+    pub fn to_absolute(&self) -> CueSheet {
+        let mut sheet = self.clone();
+        sheet.tracks = self
+            .tracks
+            .iter()
+            .zip(self.index_positions(true))
+            .map(|(track, positions)| track.clone().with_indexes(positions))
+            .collect();
+        sheet
+    }
+
+    /// Every file referenced by the sheet, in order of appearance.
+    ///
+    /// The FILE of the sheet comes first, followed by each FILE switch of the tracks.
+    pub fn files(&self) -> Vec<(&str, CueFileFormat)> {
+        let mut files = vec![(self.file.0.as_str(), self.file.1)];
+        let switches = self.tracks.iter().flat_map(|track| {
+            let index_files = track.index_files().values();
+            track
+                .file()
+                .into_iter()
+                .chain(index_files.map(|(name, format)| (name.as_str(), *format)))
+        });
+        for (name, format) in switches {
+            if files.last().map(|(last, _)| *last) != Some(name) {
+                files.push((name, format))
+            }
+        }
+        files
+    }
+
+    /// Every INDEX of the sheet in order, with the position of its file in [`CueSheet::files`]
+    pub(crate) fn index_entries(&self, sum: bool) -> Vec<IndexEntry> {
+        let mut current = self.file.0.as_str();
+        let mut file = 0;
+        let mut entries = Vec::new();
+        for (track, positions) in self.tracks.iter().zip(self.index_positions(sum)) {
+            for (i, (index, position)) in positions.into_iter().enumerate() {
+                let track_file = track.file().filter(|_| i == 0).map(|(name, _)| name);
+                let index_file = track
+                    .index_files()
+                    .get(&index)
+                    .map(|(name, _)| name.as_str());
+                for name in track_file.into_iter().chain(index_file) {
+                    if name != current {
+                        current = name;
+                        file += 1;
+                    }
+                }
+                entries.push(IndexEntry {
+                    track: track.number(),
+                    index,
+                    file,
+                    position,
+                })
+            }
+        }
+        entries
+    }
+
+    /// Unknown commands of the sheet scope, in the order they were added
+    pub fn unknown_commands(&self) -> impl Iterator<Item = &UnknownCommand> {
+        self.unknown_commands.iter()
+    }
+
+    pub fn cd_text_order(&self) -> CdTextOrder {
+        self.cd_text_order
+    }
+
+    /// CD-Text values in the order they were added
+    pub fn cd_texts(&self) -> impl Iterator<Item = &CueCdText> {
+        self.cd_texts.iter()
+    }
+
+    /// Value of the CD-Text command `key`, such as `TITLE`
+    pub fn cd_text(&self, key: &str) -> Option<&str> {
+        self.cd_texts
+            .iter()
+            .find(|cd_text| cd_text.key() == key)
+            .map(CueCdText::value)
+    }
+
+    /// REM entries in the order of the sheet
+    pub fn rems(&self) -> impl Iterator<Item = &RemEntry> {
+        self.rems.iter()
+    }
+
+    /// Value of the first REM entry `key`, such as `DATE`, ignoring case
+    pub fn rem(&self, key: &str) -> Option<&str> {
+        self.rems
+            .iter()
+            .filter(|rem| rem.is(key))
+            .find_map(RemEntry::value)
+    }
+
+    /// Values of every REM entry `key`, ignoring case
+    pub fn rem_values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        self.rems
+            .iter()
+            .filter(move |rem| rem.is(key))
+            .filter_map(RemEntry::value)
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file.0
+    }
+
+    pub fn file_format(&self) -> CueFileFormat {
+        self.file.1
+    }
+
+    pub(crate) fn set_file(&mut self, filename: &str, format: CueFileFormat) -> &mut Self {
+        self.file = (filename.to_string(), format);
+        self
+    }
+
+    /// Find the file referenced by FILE relative to the sheet located at `cue_path`.
+    ///
+    /// The lookup falls back to a case-insensitive match, and then to a file with
+    /// the same name but another audio extension. Paths that escape the sheet
+    /// directory are refused.
+    pub fn resolve_file<P: AsRef<Path>>(
+        &self,
+        cue_path: P,
+    ) -> Result<FileResolution, ResolveError> {
+        let dir = cue_file_resolver::sheet_directory(cue_path.as_ref());
+        cue_file_resolver::resolve(dir, &self.file.0)
+    }
+
+    /// Resolve the FILE entry like [`CueSheet::resolve_file`] and rewrite it to the file found.
+    ///
+    /// If the file was transcoded, the FILE type is updated from its new extension.
+    pub fn relink_file<P: AsRef<Path>>(
+        &mut self,
+        cue_path: P,
+    ) -> Result<FileResolution, ResolveError> {
+        let resolution = self.resolve_file(cue_path)?;
+        if resolution.matched == FileMatch::Transcoded {
+            let format = resolution
+                .path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .and_then(CueFileFormat::from_extension);
+            if let Some(format) = format {
+                self.file.1 = format;
+            }
+        }
+        self.file.0 = resolution.relative.clone();
+        Ok(resolution)
+    }
+
+    /// Resolve the FILE entry like [`CueSheet::resolve_file`] and read its audio length.
+    pub fn probe_file<P: AsRef<Path>>(&self, cue_path: P) -> Result<AudioInfo, ProbeError> {
+        let resolution = self.resolve_file(cue_path)?;
+        AudioInfo::probe(resolution.path, self.file.1)
+    }
+}
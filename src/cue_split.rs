@@ -0,0 +1,158 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    cue_audio_info::{AudioInfo, ProbeError},
+    cue_duration::CueDuration,
+    cue_layout::GapLayout,
+    cue_pcm,
+    cue_sheet::CueSheet,
+    cue_template::CueTemplate,
+};
+
+#[derive(Debug)]
+pub enum SplitError {
+    Probe(ProbeError),
+    Io(std::io::Error),
+    /// The audio file does not hold uncompressed integer PCM samples
+    NotPcm,
+    /// The sheet references more than one file
+    NotSingleImage,
+    /// Two tracks would be written to the file with this name
+    DuplicateName(String),
+}
+
+impl Display for SplitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitError::Probe(e) => write!(f, "{}", e),
+            SplitError::Io(e) => write!(f, "{}", e),
+            SplitError::NotPcm => write!(f, "the audio file is not uncompressed PCM"),
+            SplitError::NotSingleImage => write!(f, "the sheet references more than one file"),
+            SplitError::DuplicateName(name) => {
+                write!(f, "two tracks would be written to \"{}\"", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SplitError {}
+
+impl From<ProbeError> for SplitError {
+    fn from(value: ProbeError) -> Self {
+        Self::Probe(value)
+    }
+}
+
+impl From<std::io::Error> for SplitError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl CueSheet {
+    /// Sample range of every track of a single-image sheet of `total_samples` samples.
+    ///
+    /// The audio before the first track goes to the first track unless `gaps` is
+    /// [`GapLayout::LeftOut`], which drops every INDEX 00 range.
+    pub(crate) fn track_sample_ranges(
+        &self,
+        gaps: GapLayout,
+        sample_rate: u32,
+        total_samples: u64,
+    ) -> Vec<(u32, Range<u64>)> {
+        let tracks = self.tracks().collect::<Vec<_>>();
+        let sample = |position: Option<CueDuration>| {
+            position.map_or(0, |position| position.samples(sample_rate))
+        };
+        let starts = tracks
+            .iter()
+            .map(|track| sample(track.start()))
+            .collect::<Vec<_>>();
+        let firsts = tracks
+            .iter()
+            .map(|track| sample(track.first_position()))
+            .collect::<Vec<_>>();
+        (0..tracks.len())
+            .map(|i| {
+                let (start, end) = match gaps {
+                    GapLayout::Appended => (starts[i], starts.get(i + 1)),
+                    GapLayout::Prepended => (firsts[i], firsts.get(i + 1)),
+                    GapLayout::LeftOut => (starts[i], firsts.get(i + 1)),
+                };
+                let start = match i == 0 && gaps != GapLayout::LeftOut {
+                    true => 0,
+                    false => start,
+                };
+                let end = end.copied().unwrap_or(total_samples).min(total_samples);
+                (tracks[i].number(), start.min(end)..end)
+            })
+            .collect()
+    }
+
+    /// Write one WAVE file per track of the single-image sheet at `cue_path` into `out_dir`.
+    ///
+    /// The FILE must be a PCM WAVE, AIFF or raw BINARY/MOTOROLA image. `gaps` chooses
+    /// whether INDEX 00 ranges are appended to the previous track, prepended to their
+    /// track or dropped. File names come from `template`, and nothing is written if two
+    /// tracks get the same name, ignoring case.
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn split_audio<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        cue_path: P,
+        out_dir: Q,
+        gaps: GapLayout,
+        template: &CueTemplate,
+    ) -> Result<Vec<PathBuf>, SplitError> {
+        if self.files().len() > 1 {
+            return Err(SplitError::NotSingleImage);
+        }
+        let mut names = BTreeSet::new();
+        let mut paths = Vec::new();
+        for track in self.tracks() {
+            let name = format!("{}.wav", template.format(self, track));
+            if !names.insert(name.to_lowercase()) {
+                return Err(SplitError::DuplicateName(name));
+            }
+            paths.push(out_dir.as_ref().join(name));
+        }
+        let resolution = self.resolve_file(cue_path).map_err(ProbeError::from)?;
+        let info = AudioInfo::probe(&resolution.path, self.file_format())?;
+        let pcm = info.pcm.ok_or(SplitError::NotPcm)?;
+        let mut reader = BufReader::new(File::open(&resolution.path)?);
+
+        let ranges = self.track_sample_ranges(gaps, info.sample_rate, info.total_samples);
+        let mut outputs = Vec::with_capacity(ranges.len());
+        for (path, (_, range)) in paths.into_iter().zip(ranges) {
+            let data_len = (range.end - range.start) * info.block_align();
+            let header = cue_pcm::wav_header(
+                info.channels,
+                info.sample_rate,
+                info.bits_per_sample,
+                data_len,
+            )?;
+            let mut writer = BufWriter::new(File::create(&path)?);
+            writer.write_all(&header)?;
+            cue_pcm::copy_samples(&mut reader, &mut writer, &info, pcm, range.start, range.end)?;
+            writer.flush()?;
+            outputs.push(path);
+        }
+        Ok(outputs)
+    }
+}
@@ -0,0 +1,178 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use crate::{cue_sheet::CueSheet, cue_track::CueTrack};
+
+/// Characters refused by at least one of the common filesystems
+const ILLEGAL_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Device names reserved on Windows, whatever the extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Widest padding of the track number in `{track:0N}`
+const MAX_TRACK_WIDTH: usize = 9;
+
+/// Name of the file of a track, built from its number and CD-Text values.
+///
+/// Both the `%` and the `{}` syntaxes are understood:
+///
+/// | `%` | `{}`            | value                                       |
+/// |-----|-----------------|---------------------------------------------|
+/// | `%n`| `{track:02}`    | track number, `{track}` is not padded       |
+/// | `%t`| `{title}`       | title of the track                          |
+/// | `%p`| `{performer}`   | performer of the track or of the sheet      |
+/// | `%s`| `{songwriter}`  | songwriter of the track or of the sheet     |
+/// | `%a`| `{album}`       | title of the sheet                          |
+/// | `%%`| `{{` and `}}`   | a literal `%`, `{` or `}`                   |
+///
+/// `{track:0N}` pads the track number to N digits, N being at most 9. Unknown
+/// placeholders are written as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CueTemplate {
+    template: String,
+    max_length: usize,
+}
+
+impl CueTemplate {
+    pub fn new(template: &str) -> Self {
+        Self {
+            template: template.to_string(),
+            max_length: 240,
+        }
+    }
+
+    /// Truncate the names to at most `max_length` bytes, 240 by default to leave
+    /// room for an extension under the usual 255 bytes limit
+    pub fn max_length(&mut self, max_length: usize) -> &mut Self {
+        self.max_length = max_length.max(1);
+        self
+    }
+
+    fn value(field: &str, sheet: &CueSheet, track: &CueTrack) -> Option<String> {
+        let metadata = sheet.metadata(track);
+        let value = match field {
+            "title" => metadata.title(),
+            "performer" => metadata.performer(),
+            "songwriter" => metadata.songwriter(),
+            "album" => metadata.album(),
+            _ => return None,
+        };
+        Some(value.unwrap_or_default().to_string())
+    }
+
+    /// Value of a `{}` placeholder, such as `track:02` or `title`
+    fn braced_value(placeholder: &str, sheet: &CueSheet, track: &CueTrack) -> Option<String> {
+        let (field, spec) = match placeholder.split_once(':') {
+            Some((field, spec)) => (field, Some(spec)),
+            None => (placeholder, None),
+        };
+        match (field, spec) {
+            ("track", None) => Some(track.number().to_string()),
+            ("track", Some(spec)) => {
+                let width = spec
+                    .strip_prefix('0')
+                    .unwrap_or(spec)
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|width| *width <= MAX_TRACK_WIDTH)?;
+                match spec.starts_with('0') {
+                    true => Some(format!("{:0width$}", track.number(), width = width)),
+                    false => Some(format!("{:width$}", track.number(), width = width)),
+                }
+            }
+            (field, None) => Self::value(field, sheet, track),
+            (_, Some(_)) => None,
+        }
+    }
+
+    fn expand(&self, sheet: &CueSheet, track: &CueTrack) -> String {
+        let mut name = String::new();
+        let mut rest = self.template.as_str();
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            match c {
+                '%' => {
+                    let value = match rest.chars().next() {
+                        Some('n') => Some(format!("{:02}", track.number())),
+                        Some('t') => Self::value("title", sheet, track),
+                        Some('p') => Self::value("performer", sheet, track),
+                        Some('s') => Self::value("songwriter", sheet, track),
+                        Some('a') => Self::value("album", sheet, track),
+                        Some('%') => Some("%".to_string()),
+                        _ => None,
+                    };
+                    match value {
+                        Some(value) => {
+                            name.push_str(&value);
+                            rest = &rest[1..];
+                        }
+                        None => name.push('%'),
+                    }
+                }
+                '{' if rest.starts_with('{') => {
+                    name.push('{');
+                    rest = &rest[1..];
+                }
+                '}' if rest.starts_with('}') => {
+                    name.push('}');
+                    rest = &rest[1..];
+                }
+                '{' => {
+                    let value = rest.split_once('}').and_then(|(placeholder, after)| {
+                        Self::braced_value(placeholder, sheet, track).map(|value| (value, after))
+                    });
+                    match value {
+                        Some((value, after)) => {
+                            name.push_str(&value);
+                            rest = after;
+                        }
+                        None => name.push('{'),
+                    }
+                }
+                c => name.push(c),
+            }
+        }
+        name
+    }
+
+    /// Format the name of `track`, made safe to use as a file name.
+    ///
+    /// Illegal and control characters become `_`, reserved device names get a
+    /// leading `_`, long names are cut on a character boundary and trailing dots
+    /// and spaces are removed.
+    pub fn format(&self, sheet: &CueSheet, track: &CueTrack) -> String {
+        let mut name = self
+            .expand(sheet, track)
+            .chars()
+            .map(|c| match ILLEGAL_CHARS.contains(&c) || c.is_control() {
+                true => '_',
+                false => c,
+            })
+            .collect::<String>();
+        let stem = name.split('.').next().unwrap_or_default().trim_end();
+        let reserved = RESERVED_NAMES
+            .iter()
+            .any(|reserved| stem.eq_ignore_ascii_case(reserved));
+        if reserved {
+            name.insert(0, '_');
+        }
+        let mut end = name.len().min(self.max_length);
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        match name[..end].trim_end_matches(['.', ' ']) {
+            "" => format!("{:02}", track.number()),
+            name => name.to_string(),
+        }
+    }
+}
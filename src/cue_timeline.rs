@@ -0,0 +1,176 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use crate::{
+    cue_duration::CueDuration,
+    cue_sheet::{CueSheet, IndexEntry},
+};
+
+/// Start of an INDEX
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CueBoundary {
+    pub track: u32,
+    pub index: u32,
+    /// Position of the file in [`CueSheet::files`]
+    pub file: usize,
+    /// Position in the file
+    pub position: CueDuration,
+}
+
+/// INDEX containing a position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CueLocation {
+    pub boundary: CueBoundary,
+    /// Time elapsed since the start of the INDEX
+    pub elapsed: CueDuration,
+}
+
+/// Lookup table from playback positions to tracks and indexes.
+///
+/// Lookups are binary searches, so the table can be queried on every playback tick.
+#[derive(Debug, Clone)]
+pub struct CueTimeline {
+    files: Vec<String>,
+    /// Boundaries sorted by file then by position
+    boundaries: Vec<CueBoundary>,
+    /// Start of each file in the concatenation of all files, when known
+    file_offsets: Vec<Option<CueDuration>>,
+}
+
+impl From<IndexEntry> for CueBoundary {
+    fn from(value: IndexEntry) -> Self {
+        Self {
+            track: value.track,
+            index: value.index,
+            file: value.file,
+            position: value.position,
+        }
+    }
+}
+
+impl CueSheet {
+    /// Build the lookup table of the sheet.
+    ///
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    pub fn timeline(&self) -> CueTimeline {
+        let files = self
+            .files()
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        let mut boundaries = self
+            .index_entries(false)
+            .into_iter()
+            .map(CueBoundary::from)
+            .collect::<Vec<_>>();
+        boundaries.sort_by_key(|boundary| (boundary.file, boundary.position));
+        let mut file_offsets = vec![None; files.len()];
+        file_offsets[0] = Some(CueDuration::zero());
+        CueTimeline {
+            files,
+            boundaries,
+            file_offsets,
+        }
+    }
+}
+
+impl CueTimeline {
+    /// Set the length of each file, which lets [`CueTimeline::locate`] handle positions
+    /// past the first file of a multi-FILE sheet.
+    pub fn with_file_lengths(&mut self, lengths: &[CueDuration]) -> &mut Self {
+        let mut offset = Some(CueDuration::zero());
+        for (i, file_offset) in self.file_offsets.iter_mut().enumerate() {
+            *file_offset = offset;
+            offset = offset
+                .zip(lengths.get(i))
+                .map(|(offset, length)| offset + *length);
+        }
+        self
+    }
+
+    pub fn boundaries(&self) -> &[CueBoundary] {
+        &self.boundaries
+    }
+
+    fn file_range(&self, file: usize) -> &[CueBoundary] {
+        let start = self
+            .boundaries
+            .partition_point(|boundary| boundary.file < file);
+        let end = self
+            .boundaries
+            .partition_point(|boundary| boundary.file <= file);
+        &self.boundaries[start..end]
+    }
+
+    /// Split an absolute position into a file and a position in that file.
+    fn split_absolute(&self, position: CueDuration) -> Option<(usize, CueDuration)> {
+        let file = self
+            .file_offsets
+            .partition_point(|offset| offset.is_some_and(|offset| offset <= position));
+        let file = file.checked_sub(1)?;
+        let offset = self.file_offsets[file]?;
+        Some((file, position.saturating_sub(offset)))
+    }
+
+    fn absolute(&self, boundary: &CueBoundary) -> Option<CueDuration> {
+        self.file_offsets[boundary.file].map(|offset| offset + boundary.position)
+    }
+
+    /// Find the INDEX containing `position` in the file at position `file` of [`CueSheet::files`].
+    pub fn locate_in_file(&self, file: usize, position: CueDuration) -> Option<CueLocation> {
+        let range = self.file_range(file);
+        let i = range.partition_point(|boundary| boundary.position <= position);
+        let boundary = *range.get(i.checked_sub(1)?)?;
+        Some(CueLocation {
+            boundary,
+            elapsed: position.saturating_sub(boundary.position),
+        })
+    }
+
+    /// Find the INDEX containing `position` in the file named `filename`.
+    pub fn locate_in(&self, filename: &str, position: CueDuration) -> Option<CueLocation> {
+        let file = self.files.iter().position(|name| name == filename)?;
+        self.locate_in_file(file, position)
+    }
+
+    /// Find the INDEX containing the absolute `position`, counted from the start of the first file.
+    pub fn locate(&self, position: CueDuration) -> Option<CueLocation> {
+        let (file, in_file) = self.split_absolute(position)?;
+        self.locate_in_file(file, in_file).or_else(|| {
+            // The file starts in the middle of an INDEX begun in a previous file
+            let boundary = self.previous_boundary(position)?;
+            Some(CueLocation {
+                boundary,
+                elapsed: position.saturating_sub(self.absolute(&boundary)?),
+            })
+        })
+    }
+
+    /// First boundary strictly after the absolute `position`
+    pub fn next_boundary(&self, position: CueDuration) -> Option<CueBoundary> {
+        let i = self.boundaries.partition_point(|boundary| {
+            self.absolute(boundary)
+                .is_some_and(|absolute| absolute <= position)
+        });
+        self.boundaries
+            .get(i)
+            .filter(|boundary| self.absolute(boundary).is_some())
+            .copied()
+    }
+
+    /// Last boundary strictly before the absolute `position`
+    pub fn previous_boundary(&self, position: CueDuration) -> Option<CueBoundary> {
+        let i = self.boundaries.partition_point(|boundary| {
+            self.absolute(boundary)
+                .is_some_and(|absolute| absolute < position)
+        });
+        self.boundaries.get(i.checked_sub(1)?).copied()
+    }
+}
@@ -11,23 +11,35 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use crate::{
-    cue_cd_text::CueCdText,
+    cue_cd_text::{set_cd_text, CdTextOrder, CueCdText},
+    cue_command::{repr_unknown_commands, CommandPosition, UnknownCommand},
     cue_duration::{CueDuration, DurationFormat},
+    cue_file_format::CueFileFormat,
+    cue_rem::RemEntry,
     cue_track_flag::CueTrackFlag,
     cue_track_mode::CueTrackMode,
+    util::cue_format_file,
 };
 
 #[derive(Debug, Clone)]
 pub struct CueTrack {
+    file: Option<(String, CueFileFormat)>,
     track: (u32, CueTrackMode),
     flags: BTreeSet<CueTrackFlag>,
     pregap: Option<CueDuration>,
     postgap: Option<CueDuration>,
-    cd_texts: BTreeSet<CueCdText>,
-    rems: BTreeMap<String, String>,
+    cd_texts: Vec<CueCdText>,
+    rems: Vec<RemEntry>,
     indexes: BTreeMap<u32, CueDuration>,
+    index_files: BTreeMap<u32, (String, CueFileFormat)>,
+    unknown_commands: Vec<UnknownCommand>,
 }
 
+/// Sections of the track scope, in the order they are written
+const TRACK_SECTIONS: [&str; 8] = [
+    "FILE", "TRACK", "CDTEXT", "FLAGS", "REM", "PREGAP", "POSTGAP", "INDEX",
+];
+
 #[derive(Debug, Clone, Copy)]
 pub enum ComputeKind {
     Set(CueDuration),
@@ -44,7 +56,7 @@ impl PartialEq for CueTrack {
 
 impl PartialOrd for CueTrack {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.track.0.cmp(&other.track.0))
+        Some(self.cmp(other))
     }
 }
 
@@ -56,16 +68,18 @@ impl Ord for CueTrack {
 
 impl CueTrack {
     pub fn new(track_index: u32, mode: CueTrackMode) -> Self {
-        let track = Self {
+        Self {
+            file: None,
             track: (track_index, mode),
             flags: BTreeSet::new(),
             pregap: None,
             postgap: None,
-            cd_texts: BTreeSet::new(),
-            rems: BTreeMap::new(),
+            cd_texts: Vec::new(),
+            rems: Vec::new(),
             indexes: BTreeMap::new(),
-        };
-        track
+            index_files: BTreeMap::new(),
+            unknown_commands: Vec::new(),
+        }
     }
 
     pub fn add_index(&mut self, index: u32, duration: DurationFormat) -> &mut Self {
@@ -73,83 +87,270 @@ impl CueTrack {
         self
     }
 
+    /// Switch to `filename` before the TRACK command
+    pub fn add_file(&mut self, filename: &str, format: CueFileFormat) -> &mut Self {
+        self.file = Some((filename.to_string(), format));
+        self
+    }
+
+    /// Switch to `filename` before INDEX `index`, for a track that spans two files
+    pub fn add_index_file(
+        &mut self,
+        index: u32,
+        filename: &str,
+        format: CueFileFormat,
+    ) -> &mut Self {
+        let _ = self
+            .index_files
+            .insert(index, (filename.to_string(), format));
+        self
+    }
+
     pub fn add_flag(&mut self, flag: CueTrackFlag) -> &mut Self {
         let _ = self.flags.insert(flag);
         self
     }
 
     pub fn add_pregap(&mut self, duration: DurationFormat) -> &mut Self {
-        let _ = self.pregap = Some(duration.to_duration());
+        self.pregap = Some(duration.to_duration());
         self
     }
 
     pub fn add_postgap(&mut self, duration: DurationFormat) -> &mut Self {
-        let _ = self.postgap = Some(duration.to_duration());
+        self.postgap = Some(duration.to_duration());
         self
     }
 
     pub fn add_arranger(&mut self, arranger: &str) -> &mut Self {
         let arranger = CueCdText::Arrager(arranger.to_owned());
-        let _ = self.cd_texts.insert(arranger);
+        set_cd_text(&mut self.cd_texts, arranger);
         self
     }
 
     pub fn add_composer(&mut self, composer: &str) -> &mut Self {
         let composer = CueCdText::Composer(composer.to_string());
-        let _ = self.cd_texts.insert(composer);
+        set_cd_text(&mut self.cd_texts, composer);
         self
     }
 
     pub fn add_disc_id(&mut self, composer: &str) -> &mut Self {
         let disc_id = CueCdText::DiscId(composer.to_owned());
-        let _ = self.cd_texts.insert(disc_id);
+        set_cd_text(&mut self.cd_texts, disc_id);
         self
     }
 
     pub fn add_genre(&mut self, genre: &str) -> &mut Self {
         let genre = CueCdText::Genre(genre.to_string());
-        let _ = self.cd_texts.insert(genre);
+        set_cd_text(&mut self.cd_texts, genre);
         self
     }
 
     pub fn add_iscr(&mut self, iscr: &str) -> &mut Self {
         let iscr = CueCdText::ISrc(iscr.to_string());
-        let _ = self.cd_texts.insert(iscr);
+        set_cd_text(&mut self.cd_texts, iscr);
         self
     }
 
     pub fn add_message(&mut self, message: &str) -> &mut Self {
         let message = CueCdText::Message(message.to_string());
-        let _ = self.cd_texts.insert(message);
+        set_cd_text(&mut self.cd_texts, message);
         self
     }
 
     pub fn add_performer(&mut self, performer: &str) -> &mut Self {
         let performer = CueCdText::Performer(performer.to_string());
-        let _ = self.cd_texts.insert(performer);
+        set_cd_text(&mut self.cd_texts, performer);
         self
     }
 
     pub fn add_songwriter(&mut self, songwriter: &str) -> &mut Self {
         let songwriter = CueCdText::SongWriter(songwriter.to_string());
-        let _ = self.cd_texts.insert(songwriter);
+        set_cd_text(&mut self.cd_texts, songwriter);
         self
     }
 
     pub fn add_title(&mut self, title: &str) -> &mut Self {
         let title = CueCdText::Title(title.to_owned());
-        let _ = self.cd_texts.insert(title);
+        set_cd_text(&mut self.cd_texts, title);
+        self
+    }
+
+    /// Keep the line of a command the crate does not model, written back at `position`
+    pub fn add_unknown_command(&mut self, line: &str, position: CommandPosition) -> &mut Self {
+        self.unknown_commands
+            .push(UnknownCommand::new(line, position));
         self
     }
 
+    /// Append the REM entry `key` with `value`, stored as given
     pub fn add_rem(&mut self, key: &str, value: &str) -> &mut Self {
         let key = key.to_ascii_uppercase();
-        let _ = self.rems.insert(key, value.to_owned());
+        self.rems.push(RemEntry::new(&key, Some(value)));
+        self
+    }
+
+    /// Append a REM line without value, such as a bare `REM` if `text` is empty.
+    /// The whole of `text` is kept as the key of the entry.
+    pub fn add_bare_rem(&mut self, text: &str) -> &mut Self {
+        self.rems.push(RemEntry::new(text, None));
+        self
+    }
+
+    /// Replace every REM entry `key` with a single one, at the place of the first
+    pub fn set_rem(&mut self, key: &str, value: &str) -> &mut Self {
+        let entry = RemEntry::new(&key.to_ascii_uppercase(), Some(value));
+        match self.rems.iter().position(|rem| rem.is(key)) {
+            None => self.rems.push(entry),
+            Some(i) => {
+                self.rems[i] = entry;
+                let following = self.rems.split_off(i + 1);
+                self.rems
+                    .extend(following.into_iter().filter(|rem| !rem.is(key)));
+            }
+        }
+        self
+    }
+
+    /// Remove every REM entry `key`
+    pub fn remove_rems(&mut self, key: &str) -> &mut Self {
+        self.rems.retain(|rem| !rem.is(key));
         self
     }
 }
 
 impl CueTrack {
+    pub fn number(&self) -> u32 {
+        self.track.0
+    }
+
+    pub fn flags(&self) -> impl Iterator<Item = &CueTrackFlag> {
+        self.flags.iter()
+    }
+
+    pub fn mode(&self) -> CueTrackMode {
+        self.track.1
+    }
+
+    pub fn file(&self) -> Option<(&str, CueFileFormat)> {
+        self.file
+            .as_ref()
+            .map(|(name, format)| (name.as_str(), *format))
+    }
+
+    pub fn index_files(&self) -> &BTreeMap<u32, (String, CueFileFormat)> {
+        &self.index_files
+    }
+
+    pub fn indexes(&self) -> &BTreeMap<u32, CueDuration> {
+        &self.indexes
+    }
+
+    pub(crate) fn indexes_mut(&mut self) -> &mut BTreeMap<u32, CueDuration> {
+        &mut self.indexes
+    }
+
+    pub(crate) fn set_number(&mut self, number: u32) -> &mut Self {
+        self.track.0 = number;
+        self
+    }
+
+    /// Remove the CD-Text values and REM entries
+    pub(crate) fn clear_metadata(&mut self) -> &mut Self {
+        self.cd_texts.clear();
+        self.rems.clear();
+        self
+    }
+
+    pub(crate) fn cd_texts_mut(&mut self) -> &mut Vec<CueCdText> {
+        &mut self.cd_texts
+    }
+
+    pub(crate) fn rems_mut(&mut self) -> &mut Vec<RemEntry> {
+        &mut self.rems
+    }
+
+    pub(crate) fn flags_mut(&mut self) -> &mut BTreeSet<CueTrackFlag> {
+        &mut self.flags
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: CueTrackMode) -> &mut Self {
+        self.track.1 = mode;
+        self
+    }
+
+    pub(crate) fn unknown_commands_mut(&mut self) -> &mut Vec<UnknownCommand> {
+        &mut self.unknown_commands
+    }
+
+    pub(crate) fn set_file(&mut self, file: Option<(String, CueFileFormat)>) -> &mut Self {
+        self.file = file;
+        self
+    }
+
+    pub(crate) fn index_files_mut(&mut self) -> &mut BTreeMap<u32, (String, CueFileFormat)> {
+        &mut self.index_files
+    }
+
+    pub(crate) fn pregap_mut(&mut self) -> &mut Option<CueDuration> {
+        &mut self.pregap
+    }
+
+    pub(crate) fn postgap_mut(&mut self) -> &mut Option<CueDuration> {
+        &mut self.postgap
+    }
+
+    pub(crate) fn with_indexes(mut self, indexes: BTreeMap<u32, CueDuration>) -> Self {
+        self.indexes = indexes;
+        self
+    }
+
+    /// Unknown commands of the track scope, in the order they were added
+    pub fn unknown_commands(&self) -> impl Iterator<Item = &UnknownCommand> {
+        self.unknown_commands.iter()
+    }
+
+    /// CD-Text values in the order they were added
+    pub fn cd_texts(&self) -> impl Iterator<Item = &CueCdText> {
+        self.cd_texts.iter()
+    }
+
+    /// Value of the CD-Text command `key`, such as `TITLE`
+    pub fn cd_text(&self, key: &str) -> Option<&str> {
+        self.cd_texts
+            .iter()
+            .find(|cd_text| cd_text.key() == key)
+            .map(CueCdText::value)
+    }
+
+    /// REM entries in the order of the sheet
+    pub fn rems(&self) -> impl Iterator<Item = &RemEntry> {
+        self.rems.iter()
+    }
+
+    /// Value of the first REM entry `key`, such as `DATE`, ignoring case
+    pub fn rem(&self, key: &str) -> Option<&str> {
+        self.rems
+            .iter()
+            .filter(|rem| rem.is(key))
+            .find_map(RemEntry::value)
+    }
+
+    /// Values of every REM entry `key`, ignoring case
+    pub fn rem_values<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> {
+        self.rems
+            .iter()
+            .filter(move |rem| rem.is(key))
+            .filter_map(RemEntry::value)
+    }
+
+    pub fn pregap(&self) -> Option<CueDuration> {
+        self.pregap
+    }
+
+    pub fn postgap(&self) -> Option<CueDuration> {
+        self.postgap
+    }
+
     pub fn track_offset(&self) -> CueDuration {
         self.indexes
             .iter()
@@ -158,15 +359,15 @@ impl CueTrack {
             })
     }
 
-    fn repr_cdtexts(&self, ctab: bool) -> String {
+    fn repr_cdtexts(&self, ctab: bool, order: CdTextOrder) -> String {
         let abs_tab = "  ";
         let tab = if ctab { abs_tab } else { "" };
         match self.cd_texts.is_empty() {
             true => String::new(),
             false => {
-                let s = self
-                    .cd_texts
-                    .iter()
+                let s = order
+                    .sort(&self.cd_texts)
+                    .into_iter()
                     .map(CueCdText::to_string)
                     .collect::<Vec<String>>()
                     .join(format!("\n{}{}", abs_tab, tab).as_str());
@@ -195,11 +396,10 @@ impl CueTrack {
     fn repr_rems(&self, ctab: bool) -> String {
         let abs_tab = "  ";
         let tab = if ctab { abs_tab } else { "" };
-        let mapper = |(key, value)| format!("{}{}REM {} {}\n", abs_tab, tab, key, value);
-        match self.rems.is_empty() {
-            true => String::new(),
-            false => self.rems.iter().map(mapper).collect::<Vec<_>>().join(""),
-        }
+        self.rems
+            .iter()
+            .map(|rem| format!("{}{}{}\n", abs_tab, tab, rem))
+            .collect()
     }
 
     fn repr_pregap(&self, ctab: bool) -> String {
@@ -207,15 +407,15 @@ impl CueTrack {
         let tab = if ctab { abs_tab } else { "" };
         self.pregap
             .map(|duration| format!("{}{}PREGAP {}\n", abs_tab, tab, duration))
-            .unwrap_or(String::new())
+            .unwrap_or_default()
     }
 
     fn repr_postgap(&self, ctab: bool) -> String {
         let abs_tab = "  ";
         let tab = if ctab { abs_tab } else { "" };
-        self.pregap
+        self.postgap
             .map(|duration| format!("{}{}POSTGAP {}\n", abs_tab, tab, duration))
-            .unwrap_or(String::new())
+            .unwrap_or_default()
     }
 
     fn repr_indexes(&self, ctab: bool, compute: &Option<ComputeKind>) -> String {
@@ -228,9 +428,17 @@ impl CueTrack {
         let string_of_index = |(track_index, duration)| {
             let s = compute
                 .map(|kind| compute_duration(duration, kind))
-                .unwrap_or(duration.clone())
+                .unwrap_or(*duration)
                 .to_string();
-            format!("{}{}INDEX 0{} {}", abs_tab, tab, track_index, s)
+            let str_file = self
+                .index_files
+                .get(track_index)
+                .map(|(name, format)| cue_format_file(name, *format))
+                .unwrap_or_default();
+            format!(
+                "{}{}{}INDEX {:02} {}",
+                str_file, abs_tab, tab, track_index, s
+            )
         };
         match self.indexes.is_empty() {
             true => String::new(),
@@ -244,18 +452,66 @@ impl CueTrack {
     }
 
     pub fn repr(&self, ctab: bool, compute: Option<ComputeKind>) -> String {
+        self.repr_ordered(ctab, compute, CdTextOrder::default())
+    }
+
+    pub(crate) fn repr_ordered(
+        &self,
+        ctab: bool,
+        compute: Option<ComputeKind>,
+        order: CdTextOrder,
+    ) -> String {
         let abs_tab = "  ";
         let tab = if ctab { abs_tab } else { "" };
-        let str_track = format!("{}TRACK 0{} {}\n", tab, self.track.0, self.track.1);
+        let inner_tab = format!("{}{}", abs_tab, tab);
+        let commands = |section, indent: &str| {
+            repr_unknown_commands(&self.unknown_commands, &TRACK_SECTIONS, section, indent)
+        };
+        let str_file = self
+            .file
+            .as_ref()
+            .map(|(name, format)| cue_format_file(name, *format))
+            .unwrap_or_default();
+        let str_track = format!(
+            "{}{}{}{}TRACK {:02} {}\n",
+            commands("START", tab),
+            str_file,
+            commands("FILE", tab),
+            tab,
+            self.track.0,
+            self.track.1
+        );
         let str_pregap = self.repr_pregap(ctab);
         let str_postgap = self.repr_postgap(ctab);
-        let str_cd_texts = self.repr_cdtexts(ctab);
+        let str_cd_texts = self.repr_cdtexts(ctab, order);
         let str_flags = self.repr_flags(ctab);
         let str_rem = self.repr_rems(ctab);
         let str_indexes = self.repr_indexes(ctab, &compute);
+        let str_end = format!(
+            "{}{}",
+            commands("INDEX", &inner_tab),
+            commands("END", &inner_tab)
+        );
+        let str_end = match (str_end.is_empty(), str_indexes.is_empty()) {
+            (false, false) => format!("\n{}", str_end.trim_end_matches('\n')),
+            _ => str_end,
+        };
         format!(
-            "{}{}{}{}{}{}{}",
-            str_track, str_cd_texts, str_flags, str_rem, str_pregap, str_postgap, str_indexes
+            "{}{}{}{}{}{}{}{}{}{}{}{}{}{}",
+            str_track,
+            commands("TRACK", &inner_tab),
+            str_cd_texts,
+            commands("CDTEXT", &inner_tab),
+            str_flags,
+            commands("FLAGS", &inner_tab),
+            str_rem,
+            commands("REM", &inner_tab),
+            str_pregap,
+            commands("PREGAP", &inner_tab),
+            str_postgap,
+            commands("POSTGAP", &inner_tab),
+            str_indexes,
+            str_end
         )
     }
 }
@@ -0,0 +1,155 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::path::Path;
+
+use crate::{
+    cue_audio_info::{AudioInfo, ProbeError},
+    cue_duration::CueDuration,
+    cue_file_resolver,
+    cue_sheet::{CueSheet, IndexEntry},
+    cue_track::CueTrack,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackLength {
+    pub track: u32,
+    /// Audio stored in the file between INDEX 00 and INDEX 01
+    pub pregap: CueDuration,
+    /// Audio from INDEX 01 to the start of the next track, or to the end of the file.
+    /// `None` for the last track of a file whose length is unknown.
+    pub length: Option<CueDuration>,
+    /// Virtual silence from the PREGAP command
+    pub pregap_silence: CueDuration,
+    /// Virtual silence from the POSTGAP command
+    pub postgap_silence: CueDuration,
+}
+
+impl TrackLength {
+    /// Length played for the track, including its pregap and virtual silences
+    pub fn total(&self) -> Option<CueDuration> {
+        self.length
+            .map(|length| self.pregap_silence + self.pregap + length + self.postgap_silence)
+    }
+}
+
+impl CueTrack {
+    /// Position of INDEX 01, or of the first INDEX if there is none
+    pub(crate) fn start(&self) -> Option<CueDuration> {
+        self.indexes()
+            .get(&1)
+            .or_else(|| self.indexes().values().next())
+            .copied()
+    }
+
+    /// Position of the first INDEX, which is INDEX 00 when the track has a pregap
+    pub(crate) fn first_position(&self) -> Option<CueDuration> {
+        self.indexes().values().next().copied()
+    }
+}
+
+/// Audio from `entries[from]` to `entries[to]`, or to the end of its file when `to` is
+/// past the last entry. Crossing a FILE switch needs the length of the file left.
+fn span(
+    entries: &[IndexEntry],
+    from: usize,
+    to: usize,
+    file_end: &impl Fn(usize) -> Option<CueDuration>,
+) -> Option<CueDuration> {
+    let (mut file, mut position) = (entries[from].file, entries[from].position);
+    let mut total = CueDuration::zero();
+    for entry in entries.iter().take(to + 1).skip(from + 1) {
+        if entry.file != file {
+            total = total + file_end(file)?.saturating_sub(position);
+            file = entry.file;
+            position = CueDuration::zero();
+        }
+        total = total + entry.position.saturating_sub(position);
+        position = entry.position;
+    }
+    if to >= entries.len() {
+        total = total + file_end(file)?.saturating_sub(position);
+    }
+    Some(total)
+}
+
+impl CueSheet {
+    /// Length and pregap of every track.
+    ///
+    /// INDEX values are read as absolute positions, as written by `repr(false)`.
+    /// Use [`CueSheet::to_absolute`] first for a sheet built from durations.
+    /// The last track ends at `total`. In a sheet with several files, the last track
+    /// of an earlier file has no length, see [`CueSheet::file_track_lengths`].
+    pub fn track_lengths(&self, total: Option<CueDuration>) -> Vec<TrackLength> {
+        let last = self.files().len().saturating_sub(1);
+        self.lengths(&|file| total.filter(|_| file == last))
+    }
+
+    /// [`CueSheet::track_lengths`] where `file_lengths` holds the lengths of the files
+    /// of [`CueSheet::files`]. The last track of a file ends at the end of that file,
+    /// and its length is `None` when the file is missing from `file_lengths`.
+    pub fn file_track_lengths(&self, file_lengths: &[CueDuration]) -> Vec<TrackLength> {
+        self.lengths(&|file| file_lengths.get(file).copied())
+    }
+
+    /// Lengths of the tracks, where `file_end` gives the length of a file of
+    /// [`CueSheet::files`]
+    fn lengths(&self, file_end: &impl Fn(usize) -> Option<CueDuration>) -> Vec<TrackLength> {
+        let entries = self.index_entries(false);
+        self.tracks()
+            .map(|track| {
+                let number = track.number();
+                let first = entries.iter().position(|entry| entry.track == number);
+                let (pregap, length) = match first {
+                    None => (CueDuration::zero(), None),
+                    Some(first) => {
+                        let next = first
+                            + entries[first..]
+                                .iter()
+                                .take_while(|entry| entry.track == number)
+                                .count();
+                        let start = (first..next)
+                            .find(|i| entries[*i].index == 1)
+                            .unwrap_or(first);
+                        // a pregap in a file of unknown length only counts its part in the new file
+                        let pregap = span(&entries, first, start, file_end)
+                            .unwrap_or(entries[start].position);
+                        (pregap, span(&entries, start, next, file_end))
+                    }
+                };
+                TrackLength {
+                    track: number,
+                    pregap,
+                    length,
+                    pregap_silence: track.pregap().unwrap_or(CueDuration::zero()),
+                    postgap_silence: track.postgap().unwrap_or(CueDuration::zero()),
+                }
+            })
+            .collect()
+    }
+
+    /// [`CueSheet::file_track_lengths`] with the lengths of the files referenced by the
+    /// sheet at `cue_path`.
+    pub fn track_lengths_from_file<P: AsRef<Path>>(
+        &self,
+        cue_path: P,
+    ) -> Result<Vec<TrackLength>, ProbeError> {
+        let dir = cue_file_resolver::sheet_directory(cue_path.as_ref());
+        let file_lengths = self
+            .files()
+            .into_iter()
+            .map(|(name, format)| {
+                let resolution = cue_file_resolver::resolve(dir, name)?;
+                Ok(AudioInfo::probe(resolution.path, format)?.duration())
+            })
+            .collect::<Result<Vec<_>, ProbeError>>()?;
+        Ok(self.file_track_lengths(&file_lengths))
+    }
+}
@@ -0,0 +1,153 @@
+// /////////////////////////////////////////////////////////////////////////////////////////////
+//                                                                                            //
+//  Copyright (C) 2023 Yves Ndiaye                                                            //
+//                                                                                            //
+// This Source Code Form is subject to the terms of the Mozilla Public                        //
+// License, v. 2.0. If a copy of the MPL was not distributed with this                        //
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.                                  //
+//                                                                                            //
+// /////////////////////////////////////////////////////////////////////////////////////////////
+
+use std::{fmt::Display, path::Path};
+
+use crate::{
+    cue_audio_info::{AudioInfo, ProbeError},
+    cue_duration::CueDuration,
+    cue_sheet::CueSheet,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioIssue {
+    /// An INDEX points at or past the end of the audio data
+    IndexPastEnd {
+        track: u32,
+        index: u32,
+        position: CueDuration,
+    },
+    /// The last track starts at or past the end of the audio data
+    EmptyLastTrack { track: u32 },
+    /// The track runs past the end of the audio data by `missing`
+    TruncatedTrack { track: u32, missing: CueDuration },
+    /// The audio length is not a whole number of CD frames, which hints at a non-CD source
+    NotFrameAligned { extra_samples: u64 },
+}
+
+impl Display for AudioIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioIssue::IndexPastEnd {
+                track,
+                index,
+                position,
+            } => write!(
+                f,
+                "TRACK {:02} INDEX {:02} {} is past the end of the audio",
+                track, index, position
+            ),
+            AudioIssue::EmptyLastTrack { track } => {
+                write!(f, "TRACK {:02} has no audio", track)
+            }
+            AudioIssue::TruncatedTrack { track, missing } => {
+                write!(f, "TRACK {:02} is missing {} of audio", track, missing)
+            }
+            AudioIssue::NotFrameAligned { extra_samples } => write!(
+                f,
+                "audio length is not a whole number of CD frames ({} extra samples)",
+                extra_samples
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioReport {
+    pub info: AudioInfo,
+    pub issues: Vec<AudioIssue>,
+    /// Number of samples after the end of the last track that no track covers
+    pub trailing_samples: u64,
+}
+
+impl AudioReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn trailing(&self) -> CueDuration {
+        CueDuration::from_samples(self.trailing_samples, self.info.sample_rate)
+    }
+}
+
+impl CueSheet {
+    /// Check the INDEX positions against the audio described by `info`.
+    ///
+    /// `sum` has the same meaning as in [`CueSheet::repr`]. Without it, the last track
+    /// runs to the end of the audio and nothing is left uncovered.
+    pub fn validate_against(&self, sum: bool, info: &AudioInfo) -> AudioReport {
+        let rate = info.sample_rate;
+        let total = info.total_samples;
+        let mut issues = Vec::new();
+
+        let positions = self.index_positions(sum);
+        for (track, indexes) in self.tracks().zip(positions.iter()) {
+            indexes
+                .iter()
+                .filter(|(_, position)| position.samples(rate) >= total)
+                .for_each(|(index, position)| {
+                    issues.push(AudioIssue::IndexPastEnd {
+                        track: track.number(),
+                        index: *index,
+                        position: *position,
+                    })
+                });
+        }
+
+        let last = self.tracks().zip(positions.iter()).last();
+        let mut covered = total;
+        if let Some((track, indexes)) = last {
+            let start = indexes.get(&1).or(indexes.values().next());
+            if start.is_none_or(|start| start.samples(rate) >= total) {
+                issues.push(AudioIssue::EmptyLastTrack {
+                    track: track.number(),
+                })
+            }
+            if sum {
+                let end = indexes
+                    .values()
+                    .zip(track.indexes().values())
+                    .next_back()
+                    .map_or(CueDuration::zero(), |(start, length)| *start + *length);
+                let end_samples = end.samples(rate);
+                if end_samples > total {
+                    let missing = CueDuration::from_frames(end.frames() - info.duration().frames());
+                    issues.push(AudioIssue::TruncatedTrack {
+                        track: track.number(),
+                        missing,
+                    })
+                }
+                covered = end_samples.min(total);
+            }
+        }
+
+        let extra_samples = total - info.duration().samples(rate);
+        if extra_samples != 0 {
+            issues.push(AudioIssue::NotFrameAligned { extra_samples })
+        }
+
+        AudioReport {
+            info: *info,
+            issues,
+            trailing_samples: total - covered,
+        }
+    }
+
+    /// Resolve and probe the FILE entry of the sheet at `cue_path`, then check the INDEX
+    /// positions against it like [`CueSheet::validate_against`].
+    pub fn validate_audio<P: AsRef<Path>>(
+        &self,
+        sum: bool,
+        cue_path: P,
+    ) -> Result<AudioReport, ProbeError> {
+        let info = self.probe_file(cue_path)?;
+        Ok(self.validate_against(sum, &info))
+    }
+}
@@ -8,27 +8,97 @@
 //                                                                                            //
 // /////////////////////////////////////////////////////////////////////////////////////////////
 
+pub(crate) mod cue_audio_info;
 pub(crate) mod cue_cd_text;
+pub(crate) mod cue_command;
+pub(crate) mod cue_concat;
+pub(crate) mod cue_diff;
 pub(crate) mod cue_duration;
+pub(crate) mod cue_edit;
 pub(crate) mod cue_file_format;
+pub(crate) mod cue_file_resolver;
+pub(crate) mod cue_htoa;
+pub(crate) mod cue_join;
+pub(crate) mod cue_layout;
+pub(crate) mod cue_metadata;
+pub(crate) mod cue_patch;
+pub(crate) mod cue_pcm;
+pub(crate) mod cue_playback;
+pub(crate) mod cue_pregap;
+pub(crate) mod cue_rem;
+pub(crate) mod cue_retime;
 pub(crate) mod cue_sheet;
+pub(crate) mod cue_split;
+pub(crate) mod cue_template;
+pub(crate) mod cue_timeline;
 pub(crate) mod cue_track;
 pub(crate) mod cue_track_flag;
+pub(crate) mod cue_track_length;
 pub(crate) mod cue_track_mode;
+pub(crate) mod cue_validation;
 pub(crate) mod util;
 
-pub use crate::cue_cd_text::CueCdText;
-pub use crate::cue_duration::{CueDuration, DurationFormat};
+pub use crate::cue_audio_info::{AudioContainer, AudioInfo, PcmData, ProbeError};
+pub use crate::cue_cd_text::{CdTextOrder, CueCdText};
+pub use crate::cue_command::{CommandPosition, UnknownCommand};
+pub use crate::cue_concat::{ConcatError, ConcatLayout, MergePolicy};
+pub use crate::cue_diff::{CueChange, CueDiff, DiffScope};
+pub use crate::cue_duration::{CueDuration, DurationFormat, FRAMES_PER_SECOND};
+pub use crate::cue_edit::{EditError, MergeMode, SplitMetadata};
 pub use crate::cue_file_format::CueFileFormat;
+pub use crate::cue_file_resolver::{FileMatch, FileResolution, ResolveError};
+pub use crate::cue_join::{JoinError, JoinWarning};
+pub use crate::cue_layout::{GapLayout, LayoutError};
+pub use crate::cue_metadata::{MetadataSource, TrackMetadata};
+pub use crate::cue_patch::{CuePatch, PatchConflict, PatchError, PatchField, PatchOp, PatchValue};
+pub use crate::cue_playback::PlaybackSegment;
+pub use crate::cue_pregap::PregapError;
+pub use crate::cue_rem::{CueRem, RemDate, RemEntry, RemError};
+pub use crate::cue_retime::{CueShift, Rounding, ScaleReport, ShiftError, ShiftPolicy};
 pub use crate::cue_sheet::CueSheet;
+pub use crate::cue_split::SplitError;
+pub use crate::cue_template::CueTemplate;
+pub use crate::cue_timeline::{CueBoundary, CueLocation, CueTimeline};
 pub use crate::cue_track::{ComputeKind, CueTrack};
 pub use crate::cue_track_flag::CueTrackFlag;
+pub use crate::cue_track_length::TrackLength;
 pub use crate::cue_track_mode::CueTrackMode;
+pub use crate::cue_validation::{AudioIssue, AudioReport};
 
 #[cfg(test)]
 mod test {
+    use std::path::PathBuf;
+
     use crate::{CueSheet, CueTrack};
 
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cuesheet-rs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_wav(path: &std::path::Path, channels: u16, sample_rate: u32, samples: &[i16]) {
+        let data_len = (samples.len() * 2) as u32;
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&channels.to_le_bytes());
+        bytes.extend_from_slice(&sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(sample_rate * channels as u32 * 2).to_le_bytes());
+        bytes.extend_from_slice(&(channels * 2).to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        samples
+            .iter()
+            .for_each(|sample| bytes.extend_from_slice(&sample.to_le_bytes()));
+        std::fs::write(path, bytes).unwrap();
+    }
+
     #[test]
     fn test_iridescent_vision() {
         let mut dizzy = CueTrack::new(1, crate::CueTrackMode::AUDIO);
@@ -92,7 +162,7 @@ mod test {
             .add_performer("喜多村英梨")
             .add_composer("Village wood")
             .add_title("ETERNiTY")
-            .add_index(1, crate::DurationFormat::MinSecMil(4, 32, 006));
+            .add_index(1, crate::DurationFormat::MinSecMil(4, 32, 6));
 
         let mut nijiiro = CueTrack::new(10, crate::CueTrackMode::AUDIO);
         let _ = nijiiro
@@ -143,6 +213,40 @@ mod test {
         println!("{}", cuesheet.repr(true));
     }
 
+    #[test]
+    fn test_postgap() {
+        let mut track = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = track
+            .add_pregap(crate::DurationFormat::MinSec(0, 1))
+            .add_postgap(crate::DurationFormat::MinSec(0, 2))
+            .add_index(1, crate::DurationFormat::MinSec(0, 0));
+        let repr = track.repr(false, None);
+        assert!(repr.contains("PREGAP 00:01:00\n"));
+        assert!(repr.contains("POSTGAP 00:02:00\n"));
+    }
+
+    #[test]
+    fn test_repr_tracks() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first
+            .add_index(0, crate::DurationFormat::MinSec(0, 2))
+            .add_index(1, crate::DurationFormat::MinSec(3, 0));
+        let mut tenth = CueTrack::new(10, crate::CueTrackMode::AUDIO);
+        let _ = tenth
+            .add_index(0, crate::DurationFormat::MinSec(0, 1))
+            .add_index(1, crate::DurationFormat::MinSec(4, 0));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(first).add_track(tenth);
+
+        let repr = sheet.repr(true);
+        assert!(repr.contains("TRACK 10 AUDIO\n"));
+        assert!(repr.contains("INDEX 00 00:00:00\n    INDEX 01 00:02:00\n"));
+        assert!(repr.contains("INDEX 00 03:02:00\n    INDEX 01 03:03:00"));
+        assert!(sheet
+            .repr(false)
+            .contains("INDEX 00 00:01:00\n    INDEX 01 04:00:00"));
+    }
+
     #[test]
     fn test_readme() {
         let mut track = CueTrack::new(1, crate::CueTrackMode::AUDIO);
@@ -169,4 +273,1524 @@ mod test {
 
         let _ = sheet.export(true, "output.cue");
     }
+
+    #[test]
+    fn test_relink_file() {
+        let dir = temp_dir("relink");
+        std::fs::create_dir_all(dir.join("Disc 1")).unwrap();
+        std::fs::write(dir.join("Disc 1").join("Album.FLAC"), b"").unwrap();
+        let cue_path = dir.join("album.cue");
+
+        let mut sheet = CueSheet::new("disc 1\\album.wav", crate::CueFileFormat::WAVE);
+        let resolution = sheet.relink_file(&cue_path).unwrap();
+        assert_eq!(resolution.matched, crate::FileMatch::Transcoded);
+        assert_eq!(sheet.file_name(), "Disc 1/Album.FLAC");
+
+        let escape = CueSheet::new("Disc 1/../../album.wav", crate::CueFileFormat::WAVE);
+        assert!(matches!(
+            escape.resolve_file(&cue_path),
+            Err(crate::ResolveError::Escapes(_))
+        ));
+        let absolute = CueSheet::new("C:\\Music\\album.wav", crate::CueFileFormat::WAVE);
+        assert!(matches!(
+            absolute.resolve_file(&cue_path),
+            Err(crate::ResolveError::Absolute(_))
+        ));
+    }
+
+    #[test]
+    fn test_probe_audio() {
+        let dir = temp_dir("probe");
+        let wav = dir.join("image.wav");
+        write_wav(&wav, 2, 44100, &vec![0; (44100 + 588 * 3 + 10) * 2]);
+        let info = crate::AudioInfo::probe(&wav, crate::CueFileFormat::WAVE).unwrap();
+        assert_eq!(info.total_samples, 44100 + 588 * 3 + 10);
+        assert_eq!(info.duration().to_string(), "00:01:03");
+
+        let mut aiff = Vec::new();
+        aiff.extend_from_slice(b"FORM\0\0\0\x2EAIFFCOMM\0\0\0\x12");
+        aiff.extend_from_slice(&[0, 2, 0, 0, 0x01, 0x58, 0, 16]);
+        aiff.extend_from_slice(&[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+        aiff.extend_from_slice(b"SSND\0\0\x05\x88\0\0\0\0\0\0\0\0");
+        aiff.resize(aiff.len() + 0x580, 0);
+        let aiff_path = dir.join("image.aiff");
+        std::fs::write(&aiff_path, aiff).unwrap();
+        let info = crate::AudioInfo::probe(&aiff_path, crate::CueFileFormat::AIFF).unwrap();
+        assert_eq!((info.sample_rate, info.total_samples), (44100, 0x158));
+        assert!(info.pcm.unwrap().big_endian);
+
+        let aifc = |compression: &[u8; 4]| {
+            let mut aifc = Vec::new();
+            aifc.extend_from_slice(b"FORM\0\0\0\x36AIFCCOMM\0\0\0\x18");
+            aifc.extend_from_slice(&[0, 2, 0, 0, 0, 1, 0, 16]);
+            aifc.extend_from_slice(&[0x40, 0x0E, 0xAC, 0x44, 0, 0, 0, 0, 0, 0]);
+            aifc.extend_from_slice(compression);
+            aifc.extend_from_slice(&[0, 0]);
+            aifc.extend_from_slice(b"SSND\0\0\0\x0C\0\0\0\0\0\0\0\0\0\0\0\0");
+            let path = dir.join("image.aifc");
+            std::fs::write(&path, aifc).unwrap();
+            crate::AudioInfo::probe(&path, crate::CueFileFormat::AIFF)
+        };
+        assert!(aifc(b"twos").unwrap().pcm.unwrap().big_endian);
+        let sowt = aifc(b"sowt").unwrap();
+        assert_eq!(
+            (sowt.total_samples, sowt.pcm.unwrap().big_endian),
+            (1, false)
+        );
+        assert!(matches!(aifc(b"fl32"), Err(crate::ProbeError::Unsupported)));
+
+        let extensible = |sub_format: u8| {
+            let mut wav = b"RIFF\0\0\0\0WAVEfmt \x28\0\0\0\xFE\xFF\x02\0".to_vec();
+            wav.extend_from_slice(&44100u32.to_le_bytes());
+            wav.extend_from_slice(&(44100u32 * 8).to_le_bytes());
+            wav.extend_from_slice(&[8, 0, 32, 0, 22, 0, 32, 0, 3, 0, 0, 0]);
+            wav.extend_from_slice(&[sub_format, 0, 0, 0, 0, 0, 0x10, 0, 0x80, 0, 0]);
+            wav.extend_from_slice(&[0xAA, 0, 0x38, 0x9B, 0x71]);
+            wav.extend_from_slice(b"data\x08\0\0\0\0\0\0\0\0\0\0\0");
+            let path = dir.join("extensible.wav");
+            std::fs::write(&path, wav).unwrap();
+            crate::AudioInfo::probe(&path, crate::CueFileFormat::WAVE).unwrap()
+        };
+        assert!(extensible(1).pcm.is_some());
+        let float = extensible(3);
+        assert_eq!((float.total_samples, float.pcm), (1, None));
+
+        let mut flac = b"fLaC\x80\0\0\x22".to_vec();
+        flac.extend_from_slice(&[0; 10]);
+        let packed: u64 = (48000 << 44) | (1 << 41) | (23 << 36) | 96000;
+        flac.extend_from_slice(&packed.to_be_bytes());
+        flac.extend_from_slice(&[0; 16]);
+        let flac_path = dir.join("image.flac");
+        std::fs::write(&flac_path, flac).unwrap();
+        let info = crate::AudioInfo::probe(&flac_path, crate::CueFileFormat::WAVE).unwrap();
+        assert_eq!(info.container, crate::AudioContainer::Flac);
+        assert_eq!((info.channels, info.bits_per_sample), (2, 24));
+        assert_eq!(info.duration().to_string(), "00:02:00");
+
+        let mut flac = std::fs::read(&flac_path).unwrap();
+        flac[18..20].fill(0);
+        flac[20] &= 0x0F;
+        std::fs::write(&flac_path, flac).unwrap();
+        assert!(matches!(
+            crate::AudioInfo::probe(&flac_path, crate::CueFileFormat::WAVE),
+            Err(crate::ProbeError::Malformed(_))
+        ));
+        assert_eq!(crate::CueDuration::from_samples(96000, 0).frames(), 0);
+    }
+
+    #[test]
+    fn test_probe_mp3_and_raw() {
+        let dir = temp_dir("probe_mp3");
+        // MPEG-1 Layer III, 128 kbps, 44100 Hz, stereo: 417 bytes and 1152 samples per frame
+        let frame = |body: &[u8]| {
+            let mut frame = vec![0xFF, 0xFB, 0x90, 0x00];
+            frame.extend_from_slice(&[0; 32]);
+            frame.extend_from_slice(body);
+            frame.resize(417, 0);
+            frame
+        };
+        let probe = |name: &str, bytes: Vec<u8>, format| {
+            let path = dir.join(name);
+            std::fs::write(&path, bytes).unwrap();
+            crate::AudioInfo::probe(&path, format).unwrap()
+        };
+
+        let mut xing = b"Xing\0\0\0\x01\0\0\0\x64".to_vec();
+        xing.extend_from_slice(b"LAME3.100");
+        xing.resize(12 + 21, 0);
+        xing.extend_from_slice(&[36, 3, 232]);
+        let info = probe("xing.mp3", frame(&xing), crate::CueFileFormat::MP3);
+        assert_eq!(info.container, crate::AudioContainer::Mp3);
+        assert_eq!(info.total_samples, 100 * 1152 - 576 - 1000);
+
+        let mut vbri = b"VBRI".to_vec();
+        vbri.extend_from_slice(&[0; 10]);
+        vbri.extend_from_slice(&50u32.to_be_bytes());
+        let info = probe("vbri.mp3", frame(&vbri), crate::CueFileFormat::MP3);
+        assert_eq!(info.total_samples, 50 * 1152);
+
+        let frames = [frame(&[]), frame(&[]), frame(&[])].concat();
+        let info = probe("cbr.mp3", frames, crate::CueFileFormat::MP3);
+        assert_eq!((info.sample_rate, info.channels), (44100, 2));
+        assert_eq!(info.total_samples, 3 * 1152);
+
+        let mut short = frame(&[]);
+        short.truncate(40);
+        let info = probe("short.mp3", short, crate::CueFileFormat::MP3);
+        assert_eq!(info.total_samples, 1152);
+
+        let info = probe(
+            "image.bin",
+            vec![1; 2352 * 75],
+            crate::CueFileFormat::BINARY,
+        );
+        assert_eq!(info.container, crate::AudioContainer::Raw);
+        assert_eq!(info.duration().to_string(), "00:01:00");
+        assert!(!info.pcm.unwrap().big_endian);
+        let info = probe("image.raw", vec![1; 2352], crate::CueFileFormat::MOTOROLA);
+        assert_eq!(info.total_samples, 588);
+        assert!(info.pcm.unwrap().big_endian);
+    }
+
+    #[test]
+    fn test_validate_audio() {
+        let dir = temp_dir("validate");
+        write_wav(
+            &dir.join("image.wav"),
+            2,
+            44100,
+            &vec![0; (44100 * 5 + 100) * 2],
+        );
+        let cue_path = dir.join("image.cue");
+
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first.add_index(1, crate::DurationFormat::MinSec(0, 3));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second.add_index(1, crate::DurationFormat::MinSec(0, 4));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(first).add_track(second);
+
+        let report = sheet.validate_audio(true, &cue_path).unwrap();
+        assert_eq!(
+            report.issues,
+            vec![
+                crate::AudioIssue::TruncatedTrack {
+                    track: 2,
+                    missing: crate::DurationFormat::MinSec(0, 2).to_duration()
+                },
+                crate::AudioIssue::NotFrameAligned { extra_samples: 100 }
+            ]
+        );
+
+        let report = sheet.validate_audio(false, &cue_path).unwrap();
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.trailing_samples, 0);
+    }
+
+    #[test]
+    fn test_track_lengths() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first.add_index(1, crate::DurationFormat::MinSec(3, 0));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_index(0, crate::DurationFormat::MinSec(0, 2))
+            .add_index(1, crate::DurationFormat::MinSec(2, 0))
+            .add_pregap(crate::DurationFormat::MinSec(0, 1));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(first).add_track(second);
+
+        let lengths = sheet.to_absolute().track_lengths(None);
+        assert_eq!(lengths[0].length.unwrap().to_string(), "03:00:00");
+        assert_eq!(lengths[1].pregap.to_string(), "00:02:00");
+        assert_eq!(lengths[1].pregap_silence.to_string(), "00:01:00");
+        assert_eq!(lengths[1].length, None);
+
+        let total = crate::DurationFormat::MinSec(4, 0).to_duration();
+        let lengths = sheet.to_absolute().track_lengths(Some(total));
+        assert_eq!(lengths[1].length.unwrap().to_string(), "00:58:00");
+        assert_eq!(lengths[1].total().unwrap().to_string(), "01:01:00");
+
+        let mut third = CueTrack::new(3, crate::CueTrackMode::AUDIO);
+        let _ = third
+            .add_file("side b.wav", crate::CueFileFormat::WAVE)
+            .add_index(1, crate::DurationFormat::MinSec(0, 0));
+        let mut fourth = CueTrack::new(4, crate::CueTrackMode::AUDIO);
+        let _ = fourth.add_index(1, crate::DurationFormat::MinSec(1, 30));
+        let mut sheet = sheet.to_absolute();
+        let _ = sheet.add_track(third).add_track(fourth);
+        let lengths = sheet.file_track_lengths(&[total]);
+        assert_eq!(lengths[1].length.unwrap().to_string(), "00:58:00");
+        assert_eq!(lengths[2].length.unwrap().to_string(), "01:30:00");
+        assert_eq!(lengths[3].length, None);
+        let side_b = crate::DurationFormat::MinSec(2, 0).to_duration();
+        let lengths = sheet.file_track_lengths(&[total, side_b]);
+        assert_eq!(lengths[3].length.unwrap().to_string(), "00:30:00");
+        let lengths = sheet.track_lengths(Some(side_b));
+        assert_eq!(lengths[1].length, None);
+        assert_eq!(lengths[3].length.unwrap().to_string(), "00:30:00");
+    }
+
+    #[test]
+    fn test_timeline() {
+        let mut sheet = CueSheet::new("side a.wav", crate::CueFileFormat::WAVE);
+        for number in 1..=3 {
+            let mut track = CueTrack::new(number, crate::CueTrackMode::AUDIO);
+            let _ = track
+                .add_index(0, crate::DurationFormat::MinSec(number * 2, 0))
+                .add_index(1, crate::DurationFormat::MinSec(number * 2, 2));
+            let _ = sheet.add_track(track);
+        }
+        let mut side_b = CueTrack::new(4, crate::CueTrackMode::AUDIO);
+        let _ = side_b
+            .add_index(0, crate::DurationFormat::MinSec(7, 0))
+            .add_index_file(1, "side b.wav", crate::CueFileFormat::WAVE)
+            .add_index(1, crate::DurationFormat::MinSec(0, 0))
+            .add_index(2, crate::DurationFormat::MinSec(1, 0));
+        let _ = sheet.add_track(side_b);
+        assert!(sheet
+            .repr(false)
+            .contains("FILE \"side b.wav\" WAVE\n    INDEX 01 00:00:00"));
+
+        let at = |min, sec| crate::DurationFormat::MinSec(min, sec).to_duration();
+        let mut timeline = sheet.timeline();
+        let location = timeline.locate(at(4, 1)).unwrap();
+        assert_eq!((location.boundary.track, location.boundary.index), (2, 0));
+        assert_eq!(location.elapsed.to_string(), "00:01:00");
+        assert_eq!(timeline.locate(at(8, 0)).unwrap().boundary.track, 4);
+        assert_eq!(timeline.next_boundary(at(7, 30)), None);
+
+        let location = timeline.locate_in("side b.wav", at(1, 30)).unwrap();
+        assert_eq!((location.boundary.track, location.boundary.index), (4, 2));
+
+        let _ = timeline.with_file_lengths(&[at(7, 30)]);
+        let next = timeline.next_boundary(at(7, 15)).unwrap();
+        assert_eq!((next.track, next.index, next.file), (4, 1, 1));
+        let location = timeline.locate(at(8, 45)).unwrap();
+        assert_eq!((location.boundary.index, location.elapsed), (2, at(0, 15)));
+        let previous = timeline.previous_boundary(at(2, 2)).unwrap();
+        assert_eq!((previous.track, previous.index), (1, 0));
+    }
+
+    #[test]
+    fn test_playback_plan() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first.add_index(1, crate::DurationFormat::MinSec(0, 0));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_pregap(crate::DurationFormat::MinSec(0, 1))
+            .add_index(0, crate::DurationFormat::MinSec(0, 3))
+            .add_index_file(1, "b.wav", crate::CueFileFormat::WAVE)
+            .add_index(1, crate::DurationFormat::MinSec(0, 0))
+            .add_postgap(crate::DurationFormat::MinSec(0, 2));
+        let mut sheet = CueSheet::new("a.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(first).add_track(second);
+
+        let plan = sheet.playback_plan(44100, &[44100 * 4]);
+        let ranges = plan
+            .iter()
+            .map(|segment| {
+                let file = segment.file.as_deref();
+                (
+                    file,
+                    segment.start,
+                    segment.end,
+                    segment.track,
+                    segment.index,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ranges,
+            vec![
+                (Some("a.wav"), 0, Some(44100 * 3), 1, 1),
+                (None, 0, Some(44100), 2, 0),
+                (Some("a.wav"), 44100 * 3, Some(44100 * 4), 2, 0),
+                (Some("b.wav"), 0, None, 2, 1),
+                (None, 0, Some(44100 * 2), 2, 1),
+            ]
+        );
+
+        let mut late = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = late.add_index(1, crate::DurationFormat::MinSec(0, 10));
+        let mut short = CueSheet::new("short.wav", crate::CueFileFormat::WAVE);
+        let _ = short.add_track(late);
+        assert!(short.playback_plan(44100, &[44100]).is_empty());
+        let segment = crate::PlaybackSegment {
+            file: Some("short.wav".to_string()),
+            start: 44100 * 10,
+            end: Some(44100),
+            track: 1,
+            index: 1,
+        };
+        assert_eq!(segment.len(), Some(0));
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first.add_index(1, crate::DurationFormat::MinSec(0, 0));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_index(0, crate::DurationFormat::MinSec(3, 0))
+            .add_index(1, crate::DurationFormat::MinSec(3, 2));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(first).add_track(second);
+
+        let later = crate::CueShift::Samples {
+            count: 588 * 10 + 300,
+            sample_rate: 44100,
+        };
+        assert_eq!(later.frames(), Ok(11));
+        let shifted = sheet.shifted(later, crate::ShiftPolicy::Reject).unwrap();
+        assert!(shifted.repr(false).contains("INDEX 00 03:00:11"));
+        let no_rate = crate::CueShift::Samples {
+            count: 588,
+            sample_rate: 0,
+        };
+        assert_eq!(
+            sheet.shifted(no_rate, crate::ShiftPolicy::Clamp).err(),
+            Some(crate::ShiftError::ZeroSampleRate)
+        );
+
+        let earlier = crate::CueShift::Earlier(crate::DurationFormat::MinSec(0, 1).to_duration());
+        assert!(sheet.shifted(earlier, crate::ShiftPolicy::Reject).is_err());
+        let clamped = sheet.shifted(earlier, crate::ShiftPolicy::Clamp).unwrap();
+        assert!(clamped.repr(false).contains("INDEX 01 00:00:00"));
+        assert!(clamped.repr(false).contains("INDEX 01 03:01:00"));
+    }
+
+    #[test]
+    fn test_scale() {
+        let mut track = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = track
+            .add_pregap(crate::DurationFormat::MinSec(0, 2))
+            .add_index(1, crate::DurationFormat::MinSec(0, 0));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second.add_index(1, crate::DurationFormat::MinSecMil(4, 0, 40));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(track).add_track(second);
+
+        let (scaled, report) = sheet.scaled(25, 24, crate::Rounding::Nearest);
+        let repr = scaled.repr(false);
+        assert!(repr.contains("PREGAP 00:02:06"));
+        assert!(repr.contains("INDEX 01 04:10:03"));
+        assert!((report.max_error - 0.25).abs() < 1e-9);
+
+        let (_, report) = sheet.scaled(25, 24, crate::Rounding::Up);
+        assert!((report.max_error - 0.875).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pregap_conversion() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first.add_index(1, crate::DurationFormat::MinSec(0, 0));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_index(0, crate::DurationFormat::MinSec(3, 0))
+            .add_index(1, crate::DurationFormat::MinSec(3, 2));
+        let mut third = CueTrack::new(3, crate::CueTrackMode::AUDIO);
+        let _ = third.add_index(1, crate::DurationFormat::MinSec(6, 0));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(first).add_track(second).add_track(third);
+        let original = sheet.repr(false);
+
+        let _ = sheet.indexes_to_pregaps().unwrap();
+        let repr = sheet.repr(false);
+        assert!(!repr.contains("INDEX 00"));
+        assert!(repr.contains("PREGAP 00:02:00\n    INDEX 01 03:00:00"));
+        assert!(repr.contains("INDEX 01 05:58:00"));
+
+        let _ = sheet.pregaps_to_indexes().unwrap();
+        assert_eq!(sheet.repr(false), original);
+        assert_eq!(
+            sheet.index_to_pregap(4).err(),
+            Some(crate::PregapError::NoTrack(4))
+        );
+    }
+
+    #[test]
+    fn test_htoa() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first.add_index(1, crate::DurationFormat::MinSec(3, 0));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second.add_index(1, crate::DurationFormat::MinSec(4, 0));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet
+            .add_track(first)
+            .add_track(second)
+            .add_htoa(crate::DurationFormat::MinSec(0, 42));
+
+        let repr = sheet.repr(true);
+        assert!(repr.contains("INDEX 00 00:00:00\n    INDEX 01 00:42:00"));
+        assert!(repr.contains("INDEX 01 03:42:00"));
+
+        let absolute = sheet.to_absolute();
+        assert_eq!(absolute.htoa().unwrap().to_string(), "00:42:00");
+        assert_eq!(absolute.htoa_track().unwrap().number(), 0);
+        assert_eq!(CueSheet::new("", crate::CueFileFormat::WAVE).htoa(), None);
+
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second.add_index(1, crate::DurationFormat::MinSec(4, 0));
+        let mut no_first = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = no_first
+            .add_track(second)
+            .add_htoa(crate::DurationFormat::MinSec(0, 42));
+        assert!(!no_first.repr(true).contains("INDEX 00"));
+        assert_eq!(no_first.to_absolute().htoa(), None);
+    }
+
+    #[test]
+    fn test_file_per_track_layouts() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first.add_index(1, crate::DurationFormat::MinSec(0, 0));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_index(0, crate::DurationFormat::MinSec(3, 0))
+            .add_index(1, crate::DurationFormat::MinSec(3, 2));
+        let mut third = CueTrack::new(3, crate::CueTrackMode::AUDIO);
+        let _ = third.add_index(1, crate::DurationFormat::MinSec(6, 0));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(first).add_track(second).add_track(third);
+        let name = |track: &CueTrack| format!("{:02}.wav", track.number());
+        let at = |min, sec| crate::DurationFormat::MinSec(min, sec).to_duration();
+
+        let appended = sheet
+            .to_file_per_track(crate::GapLayout::Appended, name)
+            .unwrap();
+        let repr = appended.repr(false);
+        assert!(repr.starts_with("FILE \"01.wav\" WAVE\n"));
+        assert!(repr.contains("INDEX 00 03:00:00\nFILE \"02.wav\" WAVE\n    INDEX 01 00:00:00"));
+        let image = appended
+            .to_single_image(
+                "image.wav",
+                crate::CueFileFormat::WAVE,
+                &[at(3, 2), at(2, 58)],
+            )
+            .unwrap();
+        assert_eq!(image.repr(false), sheet.repr(false));
+
+        let prepended = sheet
+            .to_file_per_track(crate::GapLayout::Prepended, name)
+            .unwrap();
+        let repr = prepended.repr(false);
+        assert!(repr.contains(
+            "FILE \"02.wav\" WAVE\n  TRACK 02 AUDIO\n    INDEX 00 00:00:00\n    INDEX 01 00:02:00"
+        ));
+        let image = prepended
+            .to_single_image(
+                "image.wav",
+                crate::CueFileFormat::WAVE,
+                &[at(3, 0), at(3, 0)],
+            )
+            .unwrap();
+        assert_eq!(image.repr(false), sheet.repr(false));
+
+        let left_out = sheet
+            .to_file_per_track(crate::GapLayout::LeftOut, name)
+            .unwrap();
+        let repr = left_out.repr(false);
+        assert!(repr.contains("PREGAP 00:02:00\n    INDEX 01 00:00:00"));
+        assert!(matches!(
+            left_out.to_single_image("image.wav", crate::CueFileFormat::WAVE, &[]),
+            Err(crate::LayoutError::MissingFileLength(name)) if name == "01.wav"
+        ));
+    }
+
+    #[test]
+    fn test_split_audio() {
+        let dir = temp_dir("split");
+        let samples = (0..44100 * 3)
+            .flat_map(|i| [(i % 30000) as i16, -((i % 30000) as i16)])
+            .collect::<Vec<_>>();
+        write_wav(&dir.join("image.wav"), 2, 44100, &samples);
+
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first
+            .add_title("禁断果実")
+            .add_index(1, crate::DurationFormat::MinSec(0, 0));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_title("AC/DC")
+            .add_index(0, crate::DurationFormat::MinSec(0, 1))
+            .add_index(1, crate::DurationFormat::MinSec(0, 2));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet
+            .add_performer("喜多村英梨")
+            .add_track(first)
+            .add_track(second);
+
+        let outputs = sheet
+            .split_audio(
+                dir.join("image.cue"),
+                &dir,
+                crate::GapLayout::Appended,
+                &crate::CueTemplate::new("%n - %p - %t"),
+            )
+            .unwrap();
+        assert_eq!(outputs[0], dir.join("01 - 喜多村英梨 - 禁断果実.wav"));
+        assert_eq!(outputs[1], dir.join("02 - 喜多村英梨 - AC_DC.wav"));
+
+        let info = crate::AudioInfo::probe(&outputs[0], crate::CueFileFormat::WAVE).unwrap();
+        assert_eq!(info.total_samples, 44100 * 2);
+        let bytes = std::fs::read(&outputs[1]).unwrap();
+        let first_sample = i16::from_le_bytes([bytes[44], bytes[45]]);
+        assert_eq!(first_sample, (44100 * 2 % 30000) as i16);
+
+        let outputs = sheet
+            .split_audio(
+                dir.join("image.cue"),
+                &dir,
+                crate::GapLayout::LeftOut,
+                &crate::CueTemplate::new("%n"),
+            )
+            .unwrap();
+        let info = crate::AudioInfo::probe(&outputs[0], crate::CueFileFormat::WAVE).unwrap();
+        assert_eq!(info.total_samples, 44100);
+
+        let result = sheet.split_audio(
+            dir.join("image.cue"),
+            dir.join("same"),
+            crate::GapLayout::LeftOut,
+            &crate::CueTemplate::new("%p"),
+        );
+        assert!(matches!(
+            result,
+            Err(crate::SplitError::DuplicateName(name)) if name == "喜多村英梨.wav"
+        ));
+        assert!(!dir.join("same").exists());
+    }
+
+    #[test]
+    fn test_join_audio() {
+        let dir = temp_dir("join");
+        let first = (0..588 * 75 * 2)
+            .map(|i| (i % 1000) as i16)
+            .collect::<Vec<_>>();
+        write_wav(&dir.join("01.wav"), 1, 44100, &first);
+        write_wav(&dir.join("02.wav"), 1, 44100, &[7; 588 * 10 + 100]);
+        write_wav(&dir.join("03.wav"), 1, 44100, &[9; 588]);
+
+        let mut list = b"INFO".to_vec();
+        for (id, value) in [("INAM", "Hacking to the Gate"), ("IART", "いとうかなこ")] {
+            let mut value = value.as_bytes().to_vec();
+            value.push(0);
+            list.extend_from_slice(id.as_bytes());
+            list.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            if value.len() % 2 == 1 {
+                value.push(0);
+            }
+            list.extend_from_slice(&value);
+        }
+        let mut bytes = std::fs::read(dir.join("01.wav")).unwrap();
+        bytes.extend_from_slice(b"LIST");
+        bytes.extend_from_slice(&(list.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&list);
+        let riff_len = (bytes.len() - 8) as u32;
+        bytes[4..8].copy_from_slice(&riff_len.to_le_bytes());
+        std::fs::write(dir.join("01.wav"), bytes).unwrap();
+
+        let mut bytes = std::fs::read(dir.join("03.wav")).unwrap();
+        bytes.extend_from_slice(b"LIST\xF0\xFF\xFF\xFFINFO");
+        std::fs::write(dir.join("03.wav"), bytes).unwrap();
+
+        let inputs = ["01.wav", "02.wav", "03.wav"].map(|name| dir.join(name));
+        assert!(matches!(
+            CueSheet::join_audio(&inputs, dir.join("02.wav"), true),
+            Err(crate::JoinError::OutputIsInput(_))
+        ));
+        let (sheet, warnings) = CueSheet::join_audio(&inputs, dir.join("image.wav"), true).unwrap();
+        assert_eq!(
+            warnings,
+            vec![crate::JoinWarning::NotFrameAligned {
+                track: 2,
+                extra_samples: 100
+            }]
+        );
+        assert_eq!(sheet.file_name(), "image.wav");
+        let starts = sheet
+            .tracks()
+            .map(|track| track.indexes()[&1].frames())
+            .collect::<Vec<_>>();
+        assert_eq!(starts, vec![0, 150, 160]);
+        let first = sheet.tracks().next().unwrap();
+        assert_eq!(first.cd_text("TITLE"), Some("Hacking to the Gate"));
+        assert_eq!(first.cd_text("PERFORMER"), Some("いとうかなこ"));
+        assert_eq!(sheet.cd_text("PERFORMER"), None);
+
+        let info =
+            crate::AudioInfo::probe(dir.join("image.wav"), crate::CueFileFormat::WAVE).unwrap();
+        assert_eq!(info.total_samples, 588 * 161 + 100);
+
+        write_wav(&dir.join("stereo.wav"), 2, 44100, &[0; 588 * 2]);
+        let inputs = [dir.join("01.wav"), dir.join("stereo.wav")];
+        assert!(matches!(
+            CueSheet::join_audio(&inputs, dir.join("image.wav"), false),
+            Err(crate::JoinError::FormatMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_template() {
+        let mut track = CueTrack::new(3, crate::CueTrackMode::AUDIO);
+        let _ = track.add_title("What's up? <live>");
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_performer("Roselia").add_title("Anfang");
+
+        let percent = crate::CueTemplate::new("%n - %p - %t");
+        let braces = crate::CueTemplate::new("{track:02} - {performer} - {title}");
+        assert_eq!(
+            percent.format(&sheet, &track),
+            "03 - Roselia - What's up_ _live_"
+        );
+        assert_eq!(
+            braces.format(&sheet, &track),
+            percent.format(&sheet, &track)
+        );
+        assert_eq!(
+            crate::CueTemplate::new("{album}/{track} {{%a}} 100%").format(&sheet, &track),
+            "Anfang_3 {Anfang} 100%"
+        );
+        assert_eq!(
+            crate::CueTemplate::new("{unknown} {track:x}").format(&sheet, &track),
+            "{unknown} {track_x}"
+        );
+        assert_eq!(
+            crate::CueTemplate::new("{track:09} {track:0999999999}").format(&sheet, &track),
+            "000000003 {track_0999999999}"
+        );
+
+        let titled = |title: &str| {
+            let mut track = CueTrack::new(4, crate::CueTrackMode::AUDIO);
+            let _ = track.add_title(title);
+            track
+        };
+        let title = crate::CueTemplate::new("%t");
+        assert_eq!(title.format(&sheet, &titled("con.")), "_con");
+        assert_eq!(title.format(&sheet, &titled("Lpt1.flac")), "_Lpt1.flac");
+        assert_eq!(title.format(&sheet, &titled("")), "04");
+
+        let truncated = crate::CueTemplate::new("%t")
+            .max_length(7)
+            .format(&sheet, &titled("ああああ"));
+        assert_eq!(truncated, "ああ");
+    }
+
+    #[test]
+    fn test_effective_metadata() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first.add_title("Silhouette").add_rem("genre", "J-Rock");
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second.add_title("Blue Bird");
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet
+            .add_title("Best")
+            .add_performer("KANA-BOON")
+            .add_songwriter("谷口鮪")
+            .add_rem("GENRE", "Rock")
+            .add_rem("DATE", "2016")
+            .add_track(first)
+            .add_track(second);
+
+        let metadata = sheet.tracks_metadata().collect::<Vec<_>>();
+        assert_eq!(metadata[0].title(), Some("Silhouette"));
+        assert_eq!(metadata[0].performer(), Some("KANA-BOON"));
+        assert_eq!(metadata[0].songwriter(), Some("谷口鮪"));
+        assert_eq!(
+            metadata[0].get_with_source("genre"),
+            Some(("J-Rock", crate::MetadataSource::Track))
+        );
+        assert_eq!(
+            metadata[1].get_with_source("GENRE"),
+            Some(("Rock", crate::MetadataSource::Sheet))
+        );
+        assert_eq!(metadata[1].date(), Some("2016"));
+        assert_eq!(metadata[1].album(), Some("Best"));
+        assert!(!sheet.is_compilation());
+        assert_eq!(sheet.album_performer(), Some("KANA-BOON"));
+
+        let mut compilation = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        for (number, performer) in [(1, "FLOW"), (2, "FLOW"), (3, "Aqua Timez")] {
+            let mut track = CueTrack::new(number, crate::CueTrackMode::AUDIO);
+            let _ = track.add_performer(performer);
+            let _ = compilation.add_track(track);
+        }
+        assert!(compilation.is_compilation());
+        assert_eq!(compilation.album_performer(), None);
+        let _ = compilation.add_performer("Various Artists");
+        assert_eq!(compilation.album_performer(), Some("Various Artists"));
+        let last = compilation.tracks_metadata().last().unwrap();
+        assert_eq!(last.performer(), Some("Aqua Timez"));
+        assert_eq!(last.title(), None);
+    }
+
+    #[test]
+    fn test_typed_rems() {
+        let mut track = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = track
+            .add_rem_field(crate::CueRem::ReplayGainTrackGain(-6.4321))
+            .add_rem_field(crate::CueRem::ReplayGainTrackPeak(0.98))
+            .add_rem_field(crate::CueRem::Composer("Hiroyuki Sawano".to_string()));
+        let mut sheet = CueSheet::new("image.flac", crate::CueFileFormat::WAVE);
+        let _ = sheet
+            .add_rem_field(crate::CueRem::Genre("Soundtrack".to_string()))
+            .add_rem_field(crate::CueRem::Date(crate::RemDate::Full {
+                year: 2013,
+                month: 6,
+                day: 26,
+            }))
+            .add_rem_field(crate::CueRem::DiscId(0x8A0B2F0C))
+            .add_rem("COMMENT", "ExactAudioCopy v1.6")
+            .add_rem("replaygain_album_gain", "+1.5 dB")
+            .add_rem("LABEL", "Pony Canyon")
+            .add_track(track);
+
+        let repr = sheet.repr(false);
+        assert!(repr.contains("REM COMMENT \"ExactAudioCopy v1.6\"\n"));
+        assert!(repr.contains("REM DATE 2013-06-26\n"));
+        assert!(repr.contains("REM DISCID 8A0B2F0C\n"));
+        assert!(repr.contains("REM GENRE \"Soundtrack\"\n"));
+        assert!(repr.contains("REM LABEL \"Pony Canyon\"\n"));
+        assert!(repr.contains("REM REPLAYGAIN_ALBUM_GAIN +1.5 dB\n"));
+        assert!(repr.contains("    REM COMPOSER \"Hiroyuki Sawano\"\n"));
+        assert!(repr.contains("    REM REPLAYGAIN_TRACK_GAIN -6.43 dB\n"));
+        assert!(repr.contains("    REM REPLAYGAIN_TRACK_PEAK 0.980000\n"));
+
+        let genre = "Rock \"n\" Roll \\ Pop";
+        let mut quoted = CueSheet::new("image.flac", crate::CueFileFormat::WAVE);
+        let _ = quoted
+            .add_rem_field(crate::CueRem::Genre(genre.to_string()))
+            .add_rem_field(crate::CueRem::Comment("\"Heroes\"".to_string()))
+            .add_title("AC\\DC Live");
+        let line = quoted.rems().next().unwrap().to_string();
+        assert_eq!(line, r#"REM GENRE "Rock \"n\" Roll \ Pop""#);
+        let entry = crate::RemEntry::from_line(&line);
+        assert_eq!((entry.key(), entry.value()), ("GENRE", Some(genre)));
+        let value = line.strip_prefix("REM GENRE ").unwrap();
+        assert_eq!(
+            crate::CueRem::parse("GENRE", value),
+            Ok(crate::CueRem::Genre(genre.to_string()))
+        );
+        assert_eq!(
+            quoted.rem_field("COMMENT"),
+            Ok(Some(crate::CueRem::Comment("\"Heroes\"".to_string())))
+        );
+        assert!(quoted.repr(false).contains(r#"TITLE "AC\DC Live""#));
+        assert_eq!(
+            crate::CueRem::parse("DISCID", "+1234567"),
+            Err(crate::RemError::InvalidValue {
+                key: "DISCID".to_string(),
+                value: "+1234567".to_string()
+            })
+        );
+
+        assert_eq!(
+            sheet.rem_field("ReplayGain_Album_Gain"),
+            Ok(Some(crate::CueRem::ReplayGainAlbumGain(1.5)))
+        );
+        assert_eq!(sheet.rem_field("DISCNUMBER"), Ok(None));
+        assert!(matches!(
+            sheet.rem_field("LABEL"),
+            Err(crate::RemError::UnknownKey(_))
+        ));
+        assert_eq!(
+            crate::CueRem::parse("DATE", "2013"),
+            Ok(crate::CueRem::Date(crate::RemDate::Year(2013)))
+        );
+        for (key, value) in [
+            ("DATE", "2023-02-29"),
+            ("DATE", "13"),
+            ("DISCID", "8A0B2F0"),
+            ("DISCNUMBER", "0"),
+            ("REPLAYGAIN_TRACK_PEAK", "1.2"),
+            ("REPLAYGAIN_TRACK_GAIN", "loud"),
+        ] {
+            assert!(matches!(
+                crate::CueRem::parse(key, value),
+                Err(crate::RemError::InvalidValue { .. })
+            ));
+        }
+        assert_eq!(crate::CueRem::DiscNumber(2).to_string(), "REM DISCNUMBER 2");
+    }
+
+    #[test]
+    fn test_ordered_rems() {
+        let mut track = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = track
+            .add_rem("COMMENT", "first")
+            .add_bare_rem("")
+            .add_rem("COMMENT", "second");
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet
+            .add_rem("GENRE", "Rock")
+            .add_rem("DATE", "2001")
+            .add_rem("COMMENT", "ExactAudioCopy v1.6")
+            .add_bare_rem("Ripped without errors")
+            .add_rem("COMMENT", "Accurately ripped")
+            .add_track(track);
+
+        let repr = sheet.repr(false);
+        assert!(repr.starts_with(
+            "REM GENRE \"Rock\"\n\
+             REM DATE 2001\n\
+             REM COMMENT \"ExactAudioCopy v1.6\"\n\
+             REM Ripped without errors\n\
+             REM COMMENT \"Accurately ripped\"\n"
+        ));
+        assert!(repr.contains("    REM COMMENT \"first\"\n    REM\n    REM COMMENT \"second\"\n"));
+
+        assert_eq!(sheet.rem("comment"), Some("ExactAudioCopy v1.6"));
+        assert_eq!(
+            sheet.rem_values("COMMENT").collect::<Vec<_>>(),
+            vec!["ExactAudioCopy v1.6", "Accurately ripped"]
+        );
+        assert_eq!(sheet.rems().count(), 5);
+
+        let _ = sheet.set_rem("comment", "Edited").remove_rems("GENRE");
+        let rems = sheet
+            .rems()
+            .map(|rem| (rem.key(), rem.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rems,
+            vec![
+                ("DATE", Some("2001")),
+                ("COMMENT", Some("Edited")),
+                ("Ripped without errors", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_commands() {
+        use crate::CommandPosition::{After, End, Start};
+
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first
+            .add_title("Intro")
+            .add_unknown_command("SONGWRITER_EXTRA \"someone\"", After("TITLE".to_string()))
+            .add_unknown_command("FLAGS DATA", After("flags".to_string()))
+            .add_index(1, crate::DurationFormat::MinSec(0, 0))
+            .add_unknown_command("X-VENDOR 42", End);
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_index(1, crate::DurationFormat::MinSec(1, 0))
+            .add_unknown_command("X-NOT-WRITTEN-HERE", After("CATALOG".to_string()));
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet
+            .add_unknown_command("REM GENERATOR \"tool\"", Start)
+            .add_title("Album")
+            .add_unknown_command("TITLE \"Side A\"", After("FILE".to_string()))
+            .add_unknown_command("X-DISC-END", End)
+            .add_track(first)
+            .add_track(second);
+
+        assert_eq!(
+            sheet.repr(false),
+            "REM GENERATOR \"tool\"\n\
+             TITLE Album\n\
+             FILE \"image.wav\" WAVE\n\
+             TITLE \"Side A\"\n  \
+             TRACK 01 AUDIO\n    \
+             TITLE Intro\n    \
+             SONGWRITER_EXTRA \"someone\"\n    \
+             FLAGS DATA\n    \
+             INDEX 01 00:00:00\n    \
+             X-VENDOR 42\n  \
+             TRACK 02 AUDIO\n    \
+             INDEX 01 01:00:00\n    \
+             X-NOT-WRITTEN-HERE\n\
+             X-DISC-END"
+        );
+        assert_eq!(sheet.unknown_commands().count(), 3);
+        let first = sheet.tracks().next().unwrap();
+        assert_eq!(
+            first
+                .unknown_commands()
+                .map(|c| c.line())
+                .collect::<Vec<_>>(),
+            vec!["SONGWRITER_EXTRA \"someone\"", "FLAGS DATA", "X-VENDOR 42"]
+        );
+    }
+
+    #[test]
+    fn test_cd_text_order() {
+        let mut track = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = track
+            .add_composer("Kenji Kawai")
+            .add_title("Making of Cyborg")
+            .add_performer("Kenji")
+            .add_performer("Kenji Kawai");
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(track);
+
+        let cd_texts = |sheet: &CueSheet| {
+            sheet
+                .repr(false)
+                .lines()
+                .filter(|line| !line.contains("FILE") && !line.contains("TRACK"))
+                .map(|line| line.split_whitespace().next().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(cd_texts(&sheet), vec!["TITLE", "PERFORMER", "COMPOSER"]);
+        let _ = sheet.set_cd_text_order(crate::CdTextOrder::Insertion);
+        assert_eq!(cd_texts(&sheet), vec!["COMPOSER", "TITLE", "PERFORMER"]);
+        let _ = sheet.set_cd_text_order(crate::CdTextOrder::Alphabetical);
+        assert_eq!(cd_texts(&sheet), vec!["COMPOSER", "PERFORMER", "TITLE"]);
+
+        let track = sheet.tracks().next().unwrap();
+        assert_eq!(track.cd_texts().count(), 3);
+        assert_eq!(track.cd_text("PERFORMER"), Some("Kenji Kawai"));
+    }
+
+    #[test]
+    fn test_track_reordering() {
+        let track = |title: &str, seconds: u32| {
+            let mut track = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+            let _ = track
+                .add_title(title)
+                .add_index(1, crate::DurationFormat::MinSec(0, seconds));
+            track
+        };
+        let mut sheet = CueSheet::new("image.wav", crate::CueFileFormat::WAVE);
+        for (number, title, seconds) in [(1, "A", 10), (2, "B", 20), (3, "C", 30)] {
+            let mut track = track(title, seconds);
+            let _ = track.set_number(number);
+            let _ = sheet.add_track(track);
+        }
+        let summary = |sheet: &CueSheet| {
+            let sheet = sheet.to_absolute();
+            sheet
+                .tracks()
+                .map(|track| {
+                    let title = track.cd_text("TITLE").unwrap().to_string();
+                    (track.number(), title, track.indexes()[&1].frames() / 75)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let _ = sheet.insert_track(2, track("Bonus", 5)).unwrap();
+        assert_eq!(
+            summary(&sheet),
+            vec![
+                (1, "A".to_string(), 0),
+                (2, "Bonus".to_string(), 10),
+                (3, "B".to_string(), 15),
+                (4, "C".to_string(), 35)
+            ]
+        );
+        let removed = sheet.remove_track(1).unwrap();
+        assert_eq!(removed.cd_text("TITLE"), Some("A"));
+        let _ = sheet.move_track(1, 3).unwrap();
+        assert_eq!(
+            summary(&sheet),
+            vec![
+                (1, "B".to_string(), 0),
+                (2, "C".to_string(), 20),
+                (3, "Bonus".to_string(), 50)
+            ]
+        );
+        let _ = sheet.swap_tracks(1, 3).unwrap();
+        let titles = summary(&sheet)
+            .into_iter()
+            .map(|(_, title, _)| title)
+            .collect::<Vec<_>>();
+        assert_eq!(titles, vec!["Bonus", "C", "B"]);
+
+        let _ = sheet.insert_track(4, track("D", 1)).unwrap();
+        assert_eq!(sheet.tracks().count(), 4);
+        assert!(matches!(
+            sheet.insert_track(6, track("E", 1)),
+            Err(crate::EditError::InvalidPosition(6))
+        ));
+        assert!(matches!(
+            sheet.swap_tracks(1, 9),
+            Err(crate::EditError::NoTrack(9))
+        ));
+        for number in 5..=99 {
+            let _ = sheet.insert_track(number, track("Filler", 1)).unwrap();
+        }
+        assert!(matches!(
+            sheet.insert_track(100, track("Too many", 1)),
+            Err(crate::EditError::TooManyTracks)
+        ));
+        assert_eq!(sheet.tracks().count(), 99);
+    }
+
+    #[test]
+    fn test_split_merge_tracks() {
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = first
+            .add_title("Mix part 1")
+            .add_rem("COMMENT", "live")
+            .add_index(1, crate::DurationFormat::MinSec(0, 0))
+            .add_index(2, crate::DurationFormat::MinSec(3, 0))
+            .add_postgap(crate::DurationFormat::MinSec(0, 2));
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_title("Mix part 2")
+            .add_index(0, crate::DurationFormat::MinSec(5, 0))
+            .add_index(1, crate::DurationFormat::MinSec(5, 2));
+        let mut sheet = CueSheet::new("mix.wav", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_track(first).add_track(second);
+        let seconds = |track: &CueTrack| {
+            track
+                .indexes()
+                .iter()
+                .map(|(index, position)| (*index, position.frames() / 75))
+                .collect::<Vec<_>>()
+        };
+
+        let at = crate::CueDuration::from_frames(2 * 60 * 75);
+        let _ = sheet
+            .split_track(1, at, crate::SplitMetadata::Clear)
+            .unwrap();
+        let tracks = sheet.tracks().collect::<Vec<_>>();
+        assert_eq!(tracks.len(), 3);
+        assert_eq!(seconds(tracks[0]), vec![(1, 0)]);
+        assert_eq!(seconds(tracks[1]), vec![(1, 120), (2, 180)]);
+        assert_eq!(tracks[0].postgap(), None);
+        assert!(tracks[1].postgap().is_some());
+        assert_eq!(tracks[1].cd_text("TITLE"), None);
+        assert_eq!(tracks[1].rem("COMMENT"), None);
+        assert_eq!(tracks[2].number(), 3);
+        assert_eq!(tracks[2].cd_text("TITLE"), Some("Mix part 2"));
+
+        let outside = crate::CueDuration::from_frames(5 * 60 * 75);
+        assert_eq!(
+            sheet
+                .split_track(2, outside, crate::SplitMetadata::Copy)
+                .err(),
+            Some(crate::EditError::OutsideTrack(2))
+        );
+
+        let mut on_index = sheet.clone();
+        let at = crate::CueDuration::from_frames(3 * 60 * 75);
+        let _ = on_index
+            .split_track(2, at, crate::SplitMetadata::Clear)
+            .unwrap();
+        let tracks = on_index.tracks().collect::<Vec<_>>();
+        assert_eq!(seconds(tracks[1]), vec![(1, 120)]);
+        assert_eq!(seconds(tracks[2]), vec![(1, 180)]);
+        let mut spanning = CueSheet::new("mix.wav", crate::CueFileFormat::WAVE);
+        let mut track = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        let _ = track
+            .add_index(1, crate::DurationFormat::MinSec(0, 0))
+            .add_index_file(2, "mix part 2.wav", crate::CueFileFormat::WAVE)
+            .add_index(2, crate::DurationFormat::MinSec(0, 0));
+        let _ = spanning.add_track(track);
+        assert_eq!(
+            spanning
+                .split_track(1, at, crate::SplitMetadata::Copy)
+                .err(),
+            Some(crate::EditError::TrackSpansFiles(1))
+        );
+
+        let _ = sheet.merge_tracks(2, crate::MergeMode::SubIndexes).unwrap();
+        let tracks = sheet.tracks().collect::<Vec<_>>();
+        assert_eq!(tracks.len(), 2);
+        assert_eq!(
+            seconds(tracks[1]),
+            vec![(1, 120), (2, 180), (3, 300), (4, 302)]
+        );
+        let _ = sheet
+            .merge_tracks(1, crate::MergeMode::DropBoundary)
+            .unwrap();
+        let tracks = sheet.tracks().collect::<Vec<_>>();
+        assert_eq!(tracks.len(), 1);
+        assert_eq!(seconds(tracks[0]), vec![(1, 0)]);
+        assert_eq!(tracks[0].cd_text("TITLE"), Some("Mix part 1"));
+        assert!(tracks[0].postgap().is_some());
+        assert_eq!(
+            sheet.merge_tracks(1, crate::MergeMode::SubIndexes).err(),
+            Some(crate::EditError::NoNextTrack(1))
+        );
+
+        let mut crowded = CueSheet::new("mix.wav", crate::CueFileFormat::WAVE);
+        let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+        for index in 1..=98 {
+            let _ = first.add_index(index, crate::DurationFormat::MinSec(index, 0));
+        }
+        let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+        let _ = second
+            .add_index(0, crate::DurationFormat::MinSec(100, 0))
+            .add_index(1, crate::DurationFormat::MinSec(100, 2));
+        let _ = crowded.add_track(first).add_track(second);
+        assert_eq!(
+            crowded.merge_tracks(1, crate::MergeMode::SubIndexes).err(),
+            Some(crate::EditError::TooManyIndexes(1))
+        );
+        assert_eq!(crowded.tracks().count(), 2);
+    }
+
+    #[test]
+    fn test_extract_tracks() {
+        let mut sheet = CueSheet::new("disc.flac", crate::CueFileFormat::WAVE);
+        let _ = sheet.add_title("Double album").add_rem("DATE", "1999");
+        for number in 1..=6 {
+            let mut track = CueTrack::new(number, crate::CueTrackMode::AUDIO);
+            let _ = track
+                .add_title(&format!("Track {}", number))
+                .add_index(1, crate::DurationFormat::MinSec(number * 4, 0));
+            if number == 4 {
+                let _ = track.add_index(0, crate::DurationFormat::MinSec(15, 58));
+            }
+            let _ = sheet.add_track(track);
+        }
+
+        let extracted = sheet.extract_tracks(4..=5).unwrap();
+        assert_eq!(extracted.cd_text("TITLE"), Some("Double album"));
+        assert_eq!(extracted.rem("DATE"), Some("1999"));
+        assert_eq!(extracted.file_name(), "disc.flac");
+        let tracks = extracted
+            .tracks()
+            .map(|track| {
+                let positions = track
+                    .indexes()
+                    .values()
+                    .map(|position| position.frames())
+                    .collect::<Vec<_>>();
+                (track.number(), track.cd_text("TITLE").unwrap(), positions)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tracks,
+            vec![
+                (1, "Track 4", vec![0, 2 * 75]),
+                (2, "Track 5", vec![(4 * 60 + 2) * 75])
+            ]
+        );
+        assert_eq!(
+            sheet
+                .extract_tracks(std::ops::RangeInclusive::new(5, 4))
+                .err(),
+            Some(crate::EditError::InvalidRange(5, 4))
+        );
+        assert_eq!(
+            sheet.extract_tracks(5..=7).err(),
+            Some(crate::EditError::NoTrack(7))
+        );
+    }
+
+    #[test]
+    fn test_concat_sheets() {
+        let side = |name: &str, performer: &str, disc: &str, titles: &[&str]| {
+            let mut sheet = CueSheet::new(name, crate::CueFileFormat::WAVE);
+            let _ = sheet
+                .add_title("Vinyl")
+                .add_performer(performer)
+                .add_rem("DATE", "1977")
+                .add_rem("DISCNUMBER", disc);
+            for (i, title) in titles.iter().enumerate() {
+                let mut track = CueTrack::new(i as u32 + 1, crate::CueTrackMode::AUDIO);
+                let _ = track
+                    .add_title(title)
+                    .add_index(1, crate::DurationFormat::MinSec(i as u32 * 3, 0));
+                let _ = sheet.add_track(track);
+            }
+            sheet
+        };
+        let sheets = [
+            side("a.wav", "Artist", "1", &["A1", "A2"]),
+            side("b.wav", "Guest", "2", &["B1"]),
+        ];
+
+        assert_eq!(
+            CueSheet::concat(
+                &sheets,
+                crate::ConcatLayout::MultiFile,
+                crate::MergePolicy::Reject
+            )
+            .err(),
+            Some(crate::ConcatError::Conflict("PERFORMER".to_string()))
+        );
+
+        let sheet = CueSheet::concat(
+            &sheets,
+            crate::ConcatLayout::MultiFile,
+            crate::MergePolicy::MoveToTracks,
+        )
+        .unwrap();
+        assert_eq!(sheet.cd_text("TITLE"), Some("Vinyl"));
+        assert_eq!(sheet.cd_text("PERFORMER"), None);
+        assert_eq!(sheet.rem("DATE"), Some("1977"));
+        assert_eq!(sheet.rem("DISCNUMBER"), None);
+        let tracks = sheet
+            .tracks()
+            .map(|track| {
+                (
+                    track.number(),
+                    track.cd_text("PERFORMER").unwrap(),
+                    track.rem("DISCNUMBER").unwrap(),
+                    track.file().map(|(name, _)| name),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tracks,
+            vec![
+                (1, "Artist", "1", None),
+                (2, "Artist", "1", None),
+                (3, "Guest", "2", Some("b.wav"))
+            ]
+        );
+
+        let lengths = [crate::CueDuration::from_frames(10 * 60 * 75)];
+        let image = CueSheet::concat(
+            &sheets,
+            crate::ConcatLayout::SingleImage {
+                filename: "vinyl.wav",
+                format: crate::CueFileFormat::WAVE,
+                lengths: &lengths,
+            },
+            crate::MergePolicy::KeepFirst,
+        )
+        .unwrap();
+        assert_eq!(image.file_name(), "vinyl.wav");
+        assert_eq!(image.cd_text("PERFORMER"), Some("Artist"));
+        let starts = image
+            .tracks()
+            .map(|track| track.indexes()[&1].frames() / 75)
+            .collect::<Vec<_>>();
+        assert_eq!(starts, vec![0, 180, 600]);
+        assert_eq!(
+            CueSheet::concat(
+                &sheets,
+                crate::ConcatLayout::SingleImage {
+                    filename: "vinyl.wav",
+                    format: crate::CueFileFormat::WAVE,
+                    lengths: &[],
+                },
+                crate::MergePolicy::KeepFirst,
+            )
+            .err(),
+            Some(crate::ConcatError::MissingLength(0))
+        );
+
+        let mut commented = sheets.clone();
+        let _ = commented[0]
+            .add_bare_rem("Side A")
+            .add_rem("COMMENT", "first pressing");
+        let _ = commented[1].add_bare_rem("Side B");
+        let sheet = CueSheet::concat(
+            &commented,
+            crate::ConcatLayout::MultiFile,
+            crate::MergePolicy::KeepFirst,
+        )
+        .unwrap();
+        let rems = sheet
+            .rems()
+            .map(|rem| (rem.key(), rem.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rems,
+            vec![
+                ("DATE", Some("1977")),
+                ("Side A", None),
+                ("COMMENT", Some("first pressing")),
+                ("Side B", None)
+            ]
+        );
+        let many = vec![side("c.wav", "Artist", "3", &["C"; 50]); 2];
+        assert_eq!(
+            CueSheet::concat(
+                &many,
+                crate::ConcatLayout::MultiFile,
+                crate::MergePolicy::KeepFirst
+            )
+            .err(),
+            Some(crate::ConcatError::TooManyTracks)
+        );
+    }
+
+    #[test]
+    fn test_diff_sheets() {
+        let sheet = |title: &str, second_start: crate::DurationFormat, cd_text_order: bool| {
+            let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+            let _ = match cd_text_order {
+                true => first.add_title(title).add_performer("Perfume"),
+                false => first.add_performer("Perfume").add_title(title),
+            };
+            let _ = first.add_index(1, crate::DurationFormat::MinSec(0, 0));
+            let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+            let _ = second.add_index(1, second_start);
+            let mut sheet = CueSheet::new("GAME.wav", crate::CueFileFormat::WAVE);
+            let _ = sheet
+                .add_rem("COMMENT", "first")
+                .add_track(first)
+                .add_track(second);
+            sheet
+        };
+        let old = sheet("ポリリズム", crate::DurationFormat::MinSec(0, 4), true);
+        let same = sheet("ポリリズム", crate::DurationFormat::MinSec(0, 4), false);
+        assert!(old.diff(&same, false).is_empty());
+
+        let mut new = sheet(
+            "Polyrhythm",
+            crate::DurationFormat::MinSecMil(0, 4, 160),
+            false,
+        );
+        let _ = new.add_rem("COMMENT", "second").add_rem("DATE", "2008");
+        let mut third = CueTrack::new(3, crate::CueTrackMode::AUDIO);
+        let _ = third
+            .add_flag(crate::CueTrackFlag::DCP)
+            .add_index(1, crate::DurationFormat::MinSec(0, 8));
+        let _ = new.add_track(third);
+
+        let diff = old.diff(&new, false);
+        assert_eq!(
+            diff.to_string(),
+            "SHEET REM COMMENT: [\"first\"] -> [\"first\", \"second\"]\n\
+             SHEET REM DATE: [] -> [\"2008\"]\n\
+             TRACK 01 TITLE: \"ポリリズム\" -> \"Polyrhythm\"\n\
+             TRACK 02 INDEX 01 moved by +12 frames: 00:04:00 -> 00:04:12\n\
+             TRACK 03 AUDIO added\n\
+             TRACK 03 FLAGS: [] -> [\"DCP\"]\n\
+             TRACK 03 INDEX 01 added at 00:08:00\n"
+        );
+        assert!(diff.changes().contains(&crate::CueChange::IndexMoved {
+            track: 2,
+            index: 1,
+            old: crate::CueDuration::from_frames(300),
+            new: crate::CueDuration::from_frames(312),
+            delta: 12
+        }));
+        assert!(new
+            .diff(&old, false)
+            .changes()
+            .contains(&crate::CueChange::TrackRemoved(3)));
+
+        let titled = |titles: &[&str]| {
+            let mut sheet = CueSheet::new("GAME.wav", crate::CueFileFormat::WAVE);
+            for (i, title) in titles.iter().enumerate() {
+                let mut track = CueTrack::new(i as u32 + 1, crate::CueTrackMode::AUDIO);
+                let _ = track
+                    .add_title(title)
+                    .add_index(1, crate::DurationFormat::MinSec(0, 0));
+                let _ = sheet.add_track(track);
+            }
+            sheet
+        };
+        let old = titled(&["Polyrhythm", "Plastic Smile", "Edge"]);
+        let mut new = titled(&["Polyrhythm", "Love the World", "Plastic Smile", "Edge"]);
+        let _ = new.add_bare_rem("Ripped without errors").add_track({
+            let mut track = CueTrack::new(5, crate::CueTrackMode::AUDIO);
+            let _ = track
+                .add_title("Bonus")
+                .add_index(1, crate::DurationFormat::MinSec(0, 0))
+                .add_index_file(2, "bonus.wav", crate::CueFileFormat::WAVE)
+                .add_index(2, crate::DurationFormat::MinSec(0, 0));
+            track
+        });
+        assert_eq!(
+            old.diff(&new, false).to_string(),
+            "SHEET REM without value: [] -> [\"Ripped without errors\"]\n\
+             TRACK 03 renumbered to TRACK 04\n\
+             TRACK 02 renumbered to TRACK 03\n\
+             TRACK 02 AUDIO added\n\
+             TRACK 02 TITLE: none -> \"Love the World\"\n\
+             TRACK 02 INDEX 01 added at 00:00:00\n\
+             TRACK 05 AUDIO added\n\
+             TRACK 05 FILE before INDEX 02: none -> \"bonus.wav WAVE\"\n\
+             TRACK 05 TITLE: none -> \"Bonus\"\n\
+             TRACK 05 INDEX 01 added at 00:00:00\n\
+             TRACK 05 INDEX 02 added at 00:00:00\n"
+        );
+        let patch = crate::CuePatch::from_diff(&old.diff(&new, false)).unwrap();
+        let parsed = patch.to_string().parse::<crate::CuePatch>().unwrap();
+        assert_eq!(parsed, patch);
+        assert!(old
+            .apply_patch(&parsed)
+            .unwrap()
+            .diff(&new, false)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_patch_sheets() {
+        let sheet = |title: &str, second_start: crate::DurationFormat| {
+            let mut first = CueTrack::new(1, crate::CueTrackMode::AUDIO);
+            let _ = first
+                .add_title(title)
+                .add_index(1, crate::DurationFormat::MinSec(0, 0));
+            let mut second = CueTrack::new(2, crate::CueTrackMode::AUDIO);
+            let _ = second.add_index(1, second_start);
+            let mut sheet = CueSheet::new("GAME.wav", crate::CueFileFormat::WAVE);
+            let _ = sheet.add_track(first).add_track(second);
+            sheet
+        };
+        let old = sheet("Polyrhythm", crate::DurationFormat::MinSec(0, 4));
+        let mut new = sheet(
+            "Polyrhythm \"Live\"",
+            crate::DurationFormat::MinSecMil(0, 4, 160),
+        );
+        let _ = new.add_rem("DATE", "2008").add_catalog("4988005512345");
+        let mut third = CueTrack::new(3, crate::CueTrackMode::AUDIO);
+        let _ = third
+            .add_flag(crate::CueTrackFlag::F4CH)
+            .add_index(1, crate::DurationFormat::MinSec(0, 8));
+        let _ = new.add_track(third);
+
+        let patch = crate::CuePatch::from_diff(&old.diff(&new, false)).unwrap();
+        assert_eq!(
+            patch.to_string(),
+            "SET SHEET CATALOG - -> \"4988005512345\"\n\
+             SET SHEET REM DATE [ ] -> [ \"2008\" ]\n\
+             SET TRACK 01 CDTEXT TITLE \"Polyrhythm\" -> \"Polyrhythm \\\"Live\\\"\"\n\
+             SHIFT TRACK 02 INDEX 01 00:04:00 +12\n\
+             ADD TRACK 03 AUDIO\n\
+             SET TRACK 03 FLAGS [ ] -> [ 4CH ]\n\
+             SET TRACK 03 INDEX 01 - -> 00:08:00\n"
+        );
+        let parsed = patch.to_string().parse::<crate::CuePatch>().unwrap();
+        assert_eq!(parsed, patch);
+        let patched = old.apply_patch(&parsed).unwrap();
+        assert!(patched.diff(&new, false).is_empty());
+        let shift = "SHIFT TRACK 02 INDEX 01 00:04:00 +12".parse().unwrap();
+        let shifted = patched.apply_patch(&shift).unwrap();
+        assert!(shifted.diff(&new, false).is_empty());
+        assert_eq!(
+            crate::CuePatch::from_diff(&old.diff(&new, true)),
+            Err(crate::PatchError::SumDiff)
+        );
+
+        let edited = sheet("Polyrhythm (Remix)", crate::DurationFormat::MinSec(0, 4));
+        match edited.apply_patch(&patch) {
+            Err(crate::PatchError::Conflicts(conflicts)) => {
+                assert_eq!(conflicts.len(), 1);
+                assert_eq!(
+                    conflicts[0].found,
+                    crate::PatchValue::Text("Polyrhythm (Remix)".to_string())
+                );
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        let found = |patch: &str| match old.apply_patch(&patch.parse().unwrap()) {
+            Err(crate::PatchError::Conflicts(conflicts)) => conflicts
+                .into_iter()
+                .map(|conflict| conflict.found)
+                .collect::<Vec<_>>(),
+            other => panic!("expected a conflict, got {:?}", other),
+        };
+        let duration =
+            |frames| crate::PatchValue::Duration(crate::CueDuration::from_frames(frames));
+        assert_eq!(
+            found("SHIFT TRACK 02 INDEX 01 00:03:00 +12"),
+            vec![duration(300)]
+        );
+        assert_eq!(
+            found("SHIFT TRACK 02 INDEX 01 00:04:00 -4294967295"),
+            vec![duration(300)]
+        );
+        assert_eq!(
+            found("SET TRACK 02 INDEX 01 00:04:00 -> -"),
+            vec![duration(300)]
+        );
+        assert_eq!(
+            found("SHIFT TRACK 02 INDEX 01 00:04:00 -301"),
+            vec![duration(300)]
+        );
+        assert_eq!(
+            found("SET TRACK 01 INDEX 02 - -> 00:05:00"),
+            vec![duration(300)]
+        );
+        assert_eq!(
+            found("SET TRACK 02 FILE \"b.wav\" WAVE -> -"),
+            vec![crate::PatchValue::None]
+        );
+        assert_eq!(found("SET SHEET FILE \"GAME.wav\" WAVE -> -").len(), 1);
+        assert_eq!(
+            "SHIFT TRACK 02 INDEX 01 99999999:00:00 +1".parse::<crate::CuePatch>(),
+            Err(crate::PatchError::Parse {
+                line: 1,
+                message: "invalid duration 99999999:00:00".to_string()
+            })
+        );
+        assert_eq!(
+            "SET TRACK 01 PREGAP 00:00:00 ->".parse::<crate::CuePatch>(),
+            Err(crate::PatchError::Parse {
+                line: 1,
+                message: "unexpected end of line".to_string()
+            })
+        );
+    }
 }
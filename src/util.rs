@@ -8,6 +8,12 @@
 //                                                                                            //
 // /////////////////////////////////////////////////////////////////////////////////////////////
 
+use crate::cue_file_format::CueFileFormat;
+
+pub(crate) fn cue_format_file(name: &str, format: CueFileFormat) -> String {
+    format!("FILE \"{}\" {}\n", name, format)
+}
+
 pub(crate) fn cue_format_string_value(s: &str) -> String {
     match s.contains(' ') {
         true => format!("\"{}\"", s),